@@ -1,10 +1,18 @@
-use simple_logger;
 use std::io;
+use std::path::Path;
+use std::process;
 use std::time::Instant;
 
 use clap::Parser;
-use roogle::cli::CLI;
-use roogle::search_engine::query_processor::query_processor::QueryProcessor;
+use roogle::cli::{Command, Ranking, CLI};
+use roogle::search_engine::indexer::{index::Index, index_file, schema::Schema};
+use roogle::search_engine::query_processor::{
+    bm25::Bm25,
+    query_parser::QueryParseError,
+    query_processor::QueryProcessor,
+    scorer::{TermFrequency, TfIdf},
+    search_options::SearchOptions,
+};
 
 fn main() {
     simple_logger::init().unwrap();
@@ -12,11 +20,103 @@ fn main() {
     println!("Welcome to Roogle!");
     let args = CLI::parse();
 
-    eprint!("Indexing documents... ");
+    if let Some(Command::Verify { ref path }) = args.command {
+        match path.as_ref().or(args.index.as_ref()) {
+            Some(path) => verify(path),
+            None => eprintln!("error: no index file to verify; pass a path or --index"),
+        }
+        process::exit(1);
+    }
+
+    let schema = args.schema();
     let start_time = Instant::now();
-    let qp = QueryProcessor::new("./assets", args.stop_words);
-    let elapsed_sec = start_time.elapsed().as_secs_f64();
+    match args.index {
+        Some(ref path) if args.mmap => {
+            if args.reindex || !Path::new(path).exists() {
+                crawl(&schema).save(path).expect("failed to save index");
+            } else if args.update {
+                let mut qp = QueryProcessor::open(path).expect("failed to load index");
+                check_schema(&qp, &args, path);
+                update(&mut qp, path);
+            }
+            eprint!("Mapping index from {}... ", path);
+            let qp = QueryProcessor::open_mmap(path).expect("failed to map index");
+            println!("done!");
+            check_schema(&qp, &args, path);
+            run(qp, start_time, &args);
+        }
+        Some(ref path) if !args.reindex && Path::new(path).exists() => {
+            eprint!("Loading index from {}... ", path);
+            let mut qp = QueryProcessor::open(path).expect("failed to load index");
+            println!("done!");
+            check_schema(&qp, &args, path);
+            if args.update {
+                update(&mut qp, path);
+            }
+            run(qp, start_time, &args);
+        }
+        _ => {
+            let qp = crawl(&schema);
+            if let Some(ref path) = args.index {
+                qp.save(path).expect("failed to save index");
+            }
+            run(qp, start_time, &args);
+        }
+    }
+}
+
+fn crawl(schema: &Schema) -> QueryProcessor {
+    eprint!("Indexing documents... ");
+    let qp = QueryProcessor::new("./assets", schema.clone());
     println!("done!");
+    qp
+}
+
+/// Exits if an analysis option was passed that the index was not built
+/// with, since its queries would be analyzed differently than asked for.
+fn check_schema<I: Index>(qp: &QueryProcessor<I>, args: &CLI, path: &str) {
+    let conflicts = args.schema_conflicts(qp.schema());
+    if !conflicts.is_empty() {
+        eprintln!(
+            "error: {} was built with ({}), which conflicts with {}; rebuild it with --reindex",
+            path,
+            qp.schema(),
+            conflicts.join(", ")
+        );
+        process::exit(1);
+    }
+}
+
+fn update(qp: &mut QueryProcessor, path: &str) {
+    eprint!("Updating index... ");
+    let changes = qp.update("./assets").expect("failed to update index");
+    println!(
+        "{} added, {} changed, {} removed",
+        changes.added.len(),
+        changes.changed.len(),
+        changes.removed.len()
+    );
+    if !changes.is_empty() {
+        qp.save(path).expect("failed to save index");
+    }
+}
+
+fn run<I: Index>(mut qp: QueryProcessor<I>, start_time: Instant, args: &CLI) {
+    qp.set_options(SearchOptions {
+        max_expansions: args.max_expansions,
+        fuzzy_fallback: args.fuzzy,
+        max_regex_scan: args.max_regex_scan,
+    });
+    match args.scorer {
+        Ranking::Bm25 => qp.set_scorer(Bm25 {
+            k1: args.k1,
+            b: args.b,
+        }),
+        Ranking::TfIdf => qp.set_scorer(TfIdf),
+        Ranking::TermFrequency => qp.set_scorer(TermFrequency),
+    }
+    let qp = &qp;
+    let elapsed_sec = start_time.elapsed().as_secs_f64();
 
     println!(
         "Indexed {} documents ({} terms) in {:.2} seconds",
@@ -26,30 +126,134 @@ fn main() {
     );
     println!();
 
-    loop {
-        process_query(&qp);
+    match &args.command {
+        Some(Command::Stats { top, terms }) => print_stats(qp, *top, terms),
+        Some(Command::Verify { .. }) => unreachable!("verify does not load the index"),
+        None => loop {
+            process_query(qp, args.top_k);
+        },
+    }
+}
+
+fn verify(path: &str) -> ! {
+    eprint!("Verifying {}... ", path);
+    match index_file::verify(path) {
+        Ok(verified) => {
+            println!("ok");
+            println!(
+                "{} documents, {} terms, {} postings, {} bytes ({})",
+                verified.num_docs,
+                verified.num_terms,
+                verified.num_postings,
+                verified.file_size,
+                verified.schema
+            );
+            process::exit(0);
+        }
+        Err(e) => {
+            println!("failed");
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_stats<I: Index>(qp: &QueryProcessor<I>, top: usize, terms: &[String]) {
+    println!("{}", qp.stats(top));
+    if !terms.is_empty() {
+        println!();
+        println!("Terms (term, total freq, doc freq):");
+    }
+    for term in terms.iter() {
+        match qp.term_stats(term) {
+            Some(stats) => println!(
+                "  {:<20} {:>10} {:>8}",
+                stats.term, stats.total_freq, stats.doc_freq
+            ),
+            None => println!("  {:<20} not in the index", term),
+        }
+    }
+}
+
+/// Prints how the document at a path is ranked by a query, given the
+/// arguments of an `:explain <path> <query>` command.
+fn explain_query<I: Index>(qp: &QueryProcessor<I>, args: &str) {
+    let (path, query) = match args.split_once(char::is_whitespace) {
+        Some((path, query)) => (path, query.trim()),
+        None => {
+            println!("Usage: :explain <path> <query>");
+            return;
+        }
+    };
+    match qp.doc_id(path) {
+        Some(doc_id) => match qp.explain(query, doc_id) {
+            Ok(explanation) => print!("{}", explanation),
+            Err(e) => print_parse_error(query, &e),
+        },
+        None => println!("{} is not indexed.", path),
     }
 }
 
-fn process_query(qp: &QueryProcessor) {
+/// Prints a query parse error with a caret under the problem in the query.
+fn print_parse_error(query: &str, e: &QueryParseError) {
+    println!("Invalid query: {}", e.message);
+    println!("  {}", query.trim_end());
+    println!("  {}^", " ".repeat(e.position));
+}
+
+/// The most spelling suggestions printed for a query.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Answers a query read from stdin, showing only the best `top_k` results
+/// if it is set.
+fn process_query<I: Index>(qp: &QueryProcessor<I>, top_k: Option<usize>) {
     eprint!("Enter a query: ");
     let mut buf = String::new();
     match io::stdin().read_line(&mut buf) {
         Err(e) => println!("error: {}", e),
+        Ok(_bytes_read) if buf.trim().is_empty() => {}
         Ok(_bytes_read) => {
+            if let Some(args) = buf.trim_start().strip_prefix(":explain") {
+                if args.is_empty() || args.starts_with(char::is_whitespace) {
+                    explain_query(qp, args.trim());
+                    println!();
+                    return;
+                }
+            }
             let start_time = Instant::now();
-            let results = qp.search(&buf);
+            let results = match top_k {
+                Some(k) => qp.search_top_k(&buf, k),
+                None => qp.search(&buf),
+            };
+            let results = match results {
+                Ok(results) => results,
+                Err(e) => {
+                    print_parse_error(&buf, &e);
+                    println!();
+                    return;
+                }
+            };
             let elapsed_us = start_time.elapsed().as_micros();
+            let suggestions = qp.suggest(&buf, MAX_SUGGESTIONS);
             match results.len() {
-                0 => println!("No results found."),
+                0 if suggestions.is_empty() => println!("No results found."),
+                0 => {}
                 _ => {
                     let num = results.len();
                     for qr in results {
-                        println!("  {} ({})", qr.doc_name, qr.rank);
+                        println!("  {} ({:.3})", qr.doc_name, qr.score);
                     }
                     println!("Found {} results in {:.2} μs", num, elapsed_us);
                 }
             }
+            for (i, suggestion) in suggestions.iter().enumerate() {
+                let label = if i == 0 {
+                    "Did you mean"
+                } else {
+                    "          or"
+                };
+                println!("{}: {}", label, suggestion);
+            }
         }
     }
     println!();
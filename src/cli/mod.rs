@@ -1,10 +1,169 @@
-use clap::Parser;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+use clap::{Parser, Subcommand};
+
+use crate::search_engine::indexer::schema::{Schema, Tokenizer};
 
 /// A simple search engine written in Rust.
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct CLI {
-    /// Whether to exclude stop words from the search (default: false).
+    /// Whether to exclude stop words from the index (default: false).
     #[clap(short, long)]
     pub stop_words: bool,
+
+    /// How to split documents and queries into terms: alpha (the default) or
+    /// whitespace.
+    #[clap(short, long)]
+    pub tokenizer: Option<Tokenizer>,
+
+    /// Index and search terms without lowercasing them.
+    #[clap(short, long)]
+    pub case_sensitive: bool,
+
+    /// Path of an index file to load instead of crawling. If the file does
+    /// not exist yet, the documents are crawled and the index is saved there.
+    /// An existing index file keeps the analysis options it was built with.
+    #[clap(short, long)]
+    pub index: Option<String>,
+
+    /// Rebuild the index file even if it already exists.
+    #[clap(short, long)]
+    pub reindex: bool,
+
+    /// Re-index only the documents that changed since the index file was
+    /// saved, then save it again.
+    #[clap(short, long, requires = "index")]
+    pub update: bool,
+
+    /// Search the index file through a memory map instead of loading it.
+    #[clap(short, long, requires = "index")]
+    pub mmap: bool,
+
+    /// The most terms a wildcard term like `hair*` expands to.
+    #[clap(short = 'x', long, default_value_t = 128)]
+    pub max_expansions: usize,
+
+    /// Search for terms that are not in the index as fuzzy terms, to
+    /// tolerate typos.
+    #[clap(short, long)]
+    pub fuzzy: bool,
+
+    /// The most dictionary terms a regex term like `/hair(ed|ing)?/` is
+    /// checked against.
+    #[clap(long, default_value_t = 100_000)]
+    pub max_regex_scan: usize,
+
+    /// Show only the best K results of each query.
+    #[clap(short = 'k', long)]
+    pub top_k: Option<usize>,
+
+    /// How to rank results: bm25, tfidf or tf (the number of times a
+    /// document contains the terms).
+    #[clap(long, default_value = "bm25")]
+    pub scorer: Ranking,
+
+    /// The BM25 term frequency saturation: how much repeated occurrences of
+    /// a term keep raising the rank of a document.
+    #[clap(long, default_value_t = 1.2)]
+    pub k1: f64,
+
+    /// The BM25 length normalization, from 0 (none) to 1 (full): how much
+    /// long documents are ranked down.
+    #[clap(long, default_value_t = 0.75)]
+    pub b: f64,
+
+    /// What to do once the index is loaded (default: answer queries).
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl CLI {
+    /// The schema requested by the analysis options.
+    pub fn schema(&self) -> Schema {
+        let schema = Schema {
+            tokenizer: self.tokenizer.unwrap_or_default(),
+            lowercase: !self.case_sensitive,
+            ..Schema::default()
+        };
+        if self.stop_words {
+            schema.with_stop_words()
+        } else {
+            schema
+        }
+    }
+
+    /// Describes the analysis options that were passed but conflict with the
+    /// schema of an existing index.
+    ///
+    /// Options that were left out never conflict, since the index is searched
+    /// with its own schema.
+    pub fn schema_conflicts(&self, schema: &Schema) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if self.stop_words && schema.stop_words != Schema::default().with_stop_words().stop_words {
+            conflicts.push("--stop-words".to_string());
+        }
+        if let Some(tokenizer) = self.tokenizer.filter(|&t| t != schema.tokenizer) {
+            conflicts.push(format!("--tokenizer {}", tokenizer));
+        }
+        if self.case_sensitive && schema.lowercase {
+            conflicts.push("--case-sensitive".to_string());
+        }
+        conflicts
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print statistics about the index instead of answering queries.
+    Stats {
+        /// The number of most common terms to list.
+        #[clap(short = 'n', long, default_value_t = 20)]
+        top: usize,
+
+        /// Terms to print the document and total frequency of.
+        terms: Vec<String>,
+    },
+
+    /// Check an index file for corruption without searching it.
+    Verify {
+        /// The index file to check (default: the --index file).
+        path: Option<String>,
+    },
+}
+
+/// The ways results can be ranked from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ranking {
+    Bm25,
+    TfIdf,
+    TermFrequency,
+}
+
+impl FromStr for Ranking {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bm25" => Ok(Ranking::Bm25),
+            "tfidf" => Ok(Ranking::TfIdf),
+            "tf" => Ok(Ranking::TermFrequency),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown scorer {:?} (expected bm25, tfidf or tf)", s),
+            )),
+        }
+    }
+}
+
+impl Display for Ranking {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Ranking::Bm25 => write!(f, "bm25"),
+            Ranking::TfIdf => write!(f, "tfidf"),
+            Ranking::TermFrequency => write!(f, "tf"),
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use crate::search_engine::indexer::doc_table::DocTable;
+use crate::search_engine::indexer::index::IndexWriter;
 use crate::search_engine::indexer::mem_index::MemIndex;
 use std::io::Result;
 
@@ -7,4 +8,32 @@ use std::io::Result;
 pub trait Crawler {
     /// Crawls a set of documents and parses them into an inverted index.
     fn crawl(&self) -> Result<(DocTable, MemIndex)>;
+
+    /// Crawls the documents again and patches an existing index in place.
+    ///
+    /// Only documents that were added, changed or removed since the index
+    /// was built are parsed or deleted; unchanged documents are left alone.
+    /// Deleted documents are only tombstoned, so the index may need to be
+    /// compacted afterwards.
+    fn update(&self, doc_table: &mut DocTable, index: &mut dyn IndexWriter) -> Result<Changes>;
+}
+
+/// The documents that an incremental crawl found to be out of date.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// Documents that were not in the index before.
+    pub added: Vec<String>,
+
+    /// Documents whose contents changed and were re-indexed.
+    pub changed: Vec<String>,
+
+    /// Documents that no longer exist and were dropped from the index.
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    /// Returns whether the crawl left the index untouched.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
 }
@@ -1,28 +1,34 @@
+use std::collections::HashSet;
 use std::fs;
 use std::io::Result;
+use std::path::Path;
 
-use super::crawler::Crawler;
+use super::crawler::{Changes, Crawler};
 use crate::search_engine::indexer::doc_index::DocIndex;
 use crate::search_engine::indexer::doc_table::DocTable;
+use crate::search_engine::indexer::file_info::FileInfo;
+use crate::search_engine::indexer::index::IndexWriter;
 use crate::search_engine::indexer::mem_index::MemIndex;
+use crate::search_engine::indexer::schema::Schema;
 
 /// Crawls a filesystem and parses all files into an inverted index.
 pub struct FileSystemCrawler {
     /// The root directory to crawl.
     root: String,
 
-    /// Whether to index stop words.
-    stop_words: bool,
+    /// How to split files into terms.
+    schema: Schema,
 }
 
 impl FileSystemCrawler {
     /// Creates a new FileSystemCrawler.
     ///
-    /// The root directory is the directory to crawl.
-    pub fn new(root: &str, stop_words: bool) -> Self {
+    /// The root directory is the directory to crawl, and every file in it is
+    /// analyzed with `schema`.
+    pub fn new(root: &str, schema: &Schema) -> Self {
         Self {
             root: root.to_string(),
-            stop_words,
+            schema: schema.clone(),
         }
     }
 
@@ -35,8 +41,7 @@ impl FileSystemCrawler {
         let mut stack: Vec<String> = Vec::new();
         stack.push(self.root.to_string());
         while let Some(dir) = stack.pop() {
-            let mut entries = fs::read_dir(dir)?;
-            while let Some(entry) = entries.next() {
+            for entry in fs::read_dir(dir)? {
                 let path = entry?.path();
                 let path_string: String = path.to_str().unwrap().to_string();
                 if path.is_dir() {
@@ -48,6 +53,22 @@ impl FileSystemCrawler {
         }
         Ok(files)
     }
+
+    /// Parses a file and adds it to the doc table and inverted index.
+    fn index_file(
+        &self,
+        file_name: &str,
+        info: FileInfo,
+        doc_table: &mut DocTable,
+        index: &mut dyn IndexWriter,
+    ) -> Result<()> {
+        let doc_index: DocIndex = DocIndex::from_file(file_name, &self.schema)?;
+        let doc_id: usize = doc_table.add(file_name);
+        doc_table.set_info(doc_id, info);
+        doc_table.set_length(doc_id, doc_index.length());
+        index.add(doc_index, doc_id);
+        Ok(())
+    }
 }
 
 impl Crawler for FileSystemCrawler {
@@ -55,10 +76,59 @@ impl Crawler for FileSystemCrawler {
         let mut doc_table = DocTable::new();
         let mut mem_index = MemIndex::new();
         for file_name in self.files()?.iter() {
-            let doc_index: DocIndex = DocIndex::from_file(file_name, self.stop_words)?;
-            let doc_id: usize = doc_table.add(file_name);
-            mem_index.add(doc_index, doc_id);
+            let info = FileInfo::from_path(file_name)?;
+            self.index_file(file_name, info, &mut doc_table, &mut mem_index)?;
         }
         Ok((doc_table, mem_index))
     }
+
+    fn update(&self, doc_table: &mut DocTable, index: &mut dyn IndexWriter) -> Result<Changes> {
+        let mut changes = Changes::default();
+        let files = self.files()?;
+
+        // Drop the documents under our root that are no longer there. The
+        // root is matched by path component, so that `inc` is not mistaken
+        // for the root of `inc2/other.txt`.
+        let present: HashSet<&str> = files.iter().map(|f| f.as_str()).collect();
+        let removed: Vec<String> = doc_table
+            .names()
+            .filter(|doc| Path::new(doc).starts_with(&self.root) && !present.contains(doc))
+            .map(|doc| doc.to_string())
+            .collect();
+        for doc in removed.into_iter() {
+            if let Some(doc_id) = doc_table.remove(&doc) {
+                index.delete(doc_id);
+            }
+            changes.removed.push(doc);
+        }
+
+        for file_name in files.iter() {
+            let doc_id = match doc_table.get_id(file_name) {
+                Some(doc_id) => doc_id,
+                None => {
+                    let info = FileInfo::from_path(file_name)?;
+                    self.index_file(file_name, info, doc_table, index)?;
+                    changes.added.push(file_name.to_string());
+                    continue;
+                }
+            };
+
+            // Only hash the file if its size or modification time changed.
+            let old = doc_table.get_info(doc_id).copied().unwrap_or_default();
+            if FileInfo::stat(file_name)? == (old.mtime, old.size) {
+                continue;
+            }
+            let info = FileInfo::from_path(file_name)?;
+            if info.hash == old.hash {
+                doc_table.set_info(doc_id, info);
+                continue;
+            }
+
+            doc_table.remove(file_name);
+            index.delete(doc_id);
+            self.index_file(file_name, info, doc_table, index)?;
+            changes.changed.push(file_name.to_string());
+        }
+        Ok(changes)
+    }
 }
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::{BufReader, Read, Result};
+use std::io::{BufRead, BufReader, Result};
 use std::mem;
 
-use crate::search_engine::filters::stop_words::STOP_WORDS;
+use super::schema::{Schema, Tokenizer};
 
 /// An inverted index of word positions for a single document.
 ///
@@ -14,15 +14,16 @@ use crate::search_engine::filters::stop_words::STOP_WORDS;
 ///
 /// ```text
 /// {
-///  "my": [0, 6],
-///  "oh": [3],
+///  "my": [0, 2],
+///  "oh": [1],
 /// }
 /// ```
 ///
-/// A term is a sequence of non-whitespace characters, with leading and
-/// trailing punctuation removed. The position of each term is 0-based, so
-/// "my" occurs at positions 0 and 6, and "oh" occurs at position 3. The
-/// positions are byte offsets, not character or graphmeme offsets.
+/// How the file is split into terms depends on the schema's tokenizer. The
+/// position of each term is its 0-based index among the terms of the file, so
+/// "my" is the first and third term, and "oh" is the second. Stop words are
+/// not indexed but still take up a position, so that terms on either side of
+/// one are not mistaken for neighbors.
 pub struct DocIndex {
     pub(crate) index: HashMap<String, Vec<usize>>,
     pub(crate) name: String,
@@ -45,105 +46,151 @@ impl DocIndex {
         &self.name
     }
 
+    /// Returns the number of terms indexed for this document, counting every
+    /// occurrence of a term.
+    pub fn length(&self) -> usize {
+        self.index.values().map(Vec::len).sum()
+    }
+
     /// Parses a file into the inverted index.
     ///
     /// The inverted index is populated with the contents of the file. The
-    /// file is read line by line, and each line is split into words. For each
-    /// word, the inverted index is updated with the position of the word in the
-    /// file.
+    /// file is read byte by byte and split into terms by the schema's
+    /// tokenizer. For each term, the inverted index is updated with the
+    /// position of the term in the file.
     ///
     /// # Arguments
     /// * `filename` - The name of the file to parse.
-    /// * `stop_words` - Whether to ignore stop words.
+    /// * `schema` - How to split the file into terms.
     ///
     /// # Errors
     /// * If the file cannot be opened, then an error is returned.
     /// * If the file contains invalid bytes, then an error is returned.
     /// * If a term cannot be parsed, then an error is returned.
-    pub fn from_file(filename: &str, stop_words: bool) -> Result<Self> {
+    pub fn from_file(filename: &str, schema: &Schema) -> Result<Self> {
         // Try to open the file for reading.
-        let f = File::open(filename).expect("failed to open file");
-        let reader = BufReader::new(f);
-        let mut wordpos = Self::new(filename);
-
-        // Read the file byte-by-byte and append contents into a word vector.
-        let mut pos: usize = 0;
-        let mut word: Vec<u8> = Vec::new();
-        for maybe_byte in reader.bytes() {
-            let byte = maybe_byte.expect("failed to read byte");
-            handle_byte_alpha(byte, &mut word, &mut pos, stop_words, &mut wordpos);
-        }
+        let f = File::open(filename)?;
+        Self::from_reader(filename, BufReader::new(f), schema)
+    }
+
+    /// Parses text into the inverted index.
+    #[cfg(test)]
+    pub(crate) fn from_text(name: &str, text: &str, schema: &Schema) -> Self {
+        Self::from_reader(name, text.as_bytes(), schema).expect("reading a slice cannot fail")
+    }
+
+    /// Parses everything read from `reader` into the inverted index.
+    fn from_reader<R: BufRead>(name: &str, reader: R, schema: &Schema) -> Result<Self> {
+        let mut wordpos = Self::new(name);
+        tokenize(reader, schema, &mut |key, start| {
+            wordpos.index.entry(key).or_default().push(start);
+        })?;
         Ok(wordpos)
     }
 }
 
+/// Splits everything read from `reader` into terms with the schema's
+/// tokenizer.
+///
+/// `emit` is called with each term and its position, except for stop words.
+///
+/// # Errors
+/// * If reading fails, then an error is returned.
+pub(crate) fn tokenize<R: BufRead, F: FnMut(String, usize)>(
+    reader: R,
+    schema: &Schema,
+    emit: &mut F,
+) -> Result<()> {
+    // Read byte-by-byte and append contents into a word vector.
+    let mut pos: usize = 0;
+    let mut word: Vec<u8> = Vec::new();
+    for maybe_byte in reader.bytes() {
+        let byte = maybe_byte?;
+        handle_byte(byte, &mut word, &mut pos, schema, emit);
+    }
+    // A trailing space flushes the last term.
+    handle_byte(b' ', &mut word, &mut pos, schema, emit);
+    Ok(())
+}
+
+/// Feeds a byte to the schema's tokenizer.
+///
+/// Whenever a term ends, `emit` is called with the term and its position,
+/// unless the term is a stop word. `pos` counts the terms seen so far.
+fn handle_byte<F: FnMut(String, usize)>(
+    byte: u8,
+    word: &mut Vec<u8>,
+    pos: &mut usize,
+    schema: &Schema,
+    emit: &mut F,
+) {
+    match schema.tokenizer {
+        Tokenizer::Alpha => handle_byte_alpha(byte, word, pos, schema, emit),
+        Tokenizer::Whitespace => handle_byte_whitespace(byte, word, pos, schema, emit),
+    }
+}
+
 /// Splits words based on alphabetical characters.
-fn handle_byte_alpha(
+fn handle_byte_alpha<F: FnMut(String, usize)>(
     mut byte: u8,
     word: &mut Vec<u8>,
     pos: &mut usize,
-    stop_words: bool,
-    wordpos: &mut DocIndex,
+    schema: &Schema,
+    emit: &mut F,
 ) {
     if byte.is_ascii_alphabetic() {
-        byte.make_ascii_lowercase();
+        if schema.lowercase {
+            byte.make_ascii_lowercase();
+        }
         word.push(byte);
-    } else if word.len() > 0 {
-        let chars: Vec<u8> = mem::replace(word, Vec::new());
+    } else if !word.is_empty() {
+        let chars: Vec<u8> = mem::take(word);
         let key = String::from_utf8_lossy(&chars);
-        let start = usize::saturating_sub(*pos, chars.len());
-
-        // If we're not excluding stop words or the word isn't a stop
-        // word, then add it to the inverted index.
-        if !stop_words || !STOP_WORDS.contains(&key) {
-            wordpos
-                .index
-                .entry(key.to_string())
-                .or_insert(Vec::new())
-                .push(start);
+
+        // If the word isn't a stop word, then add it to the inverted index.
+        if !schema.is_stop_word(&key) {
+            emit(key.to_string(), *pos);
         }
+        *pos += 1;
     }
-    *pos += 1;
 }
 
 /// Splits words based on whitespace characters.
-fn _handle_byte_whitespace(
+fn handle_byte_whitespace<F: FnMut(String, usize)>(
     mut byte: u8,
     word: &mut Vec<u8>,
     pos: &mut usize,
-    stop_words: bool,
-    wordpos: &mut DocIndex,
+    schema: &Schema,
+    emit: &mut F,
 ) {
     // If the byte is *not* a whitespace character, then append it to
     // our vector of bytes (the "word vector").
-    if !char::is_whitespace(byte as char) {
-        byte.make_ascii_lowercase();
+    if !byte.is_ascii_whitespace() {
+        if schema.lowercase {
+            byte.make_ascii_lowercase();
+        }
         word.push(byte);
 
     // If the byte is a whitespace character, then we *might* have
     // reached the end of a word (ie. the word vector is not empty)
-    } else if word.len() > 0 {
+    } else if !word.is_empty() {
         // To flush the word vector, replace it with an empty vector,
         // then parse the old vector into a String, trim punctuation,
         // and add it to the inverted index.
-        let chars: Vec<u8> = mem::replace(word, Vec::new());
+        let chars: Vec<u8> = mem::take(word);
         let raw_key = String::from_utf8_lossy(&chars);
         let is_punctuation = |c: char| c.is_ascii_punctuation();
         let key = raw_key.trim_matches(is_punctuation);
-        // The start position of the word (protect against empty words)
-        let start = usize::saturating_sub(*pos, chars.len());
-
-        // If we're not excluding stop words or the word isn't a stop
-        // word, then add it to the inverted index.
-        if !stop_words || !STOP_WORDS.contains(key) {
-            wordpos
-                .index
-                .entry(key.to_string())
-                .or_insert(Vec::new())
-                .push(start);
+        if key.is_empty() {
+            return;
         }
+
+        // If the word isn't a stop word, then add it to the inverted index.
+        if !schema.is_stop_word(key) {
+            emit(key.to_string(), *pos);
+        }
+        *pos += 1;
     }
-    *pos += 1;
 }
 
 impl Display for DocIndex {
@@ -157,3 +204,22 @@ impl Debug for DocIndex {
         write!(f, "{:?}", self.index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn last_term_without_trailing_separator() {
+        let path = env::temp_dir().join(format!("roogle-last-term-{}.txt", std::process::id()));
+        fs::write(&path, "steve hairington").unwrap();
+        let doc_index = DocIndex::from_file(path.to_str().unwrap(), &Schema::default());
+        fs::remove_file(&path).unwrap();
+
+        let doc_index = doc_index.unwrap();
+        assert_eq!(Some(&vec![1]), doc_index.index.get("hairington"));
+        assert_eq!(2, doc_index.length());
+    }
+}
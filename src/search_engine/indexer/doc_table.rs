@@ -1,5 +1,8 @@
-use std::collections::HashMap; 
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::mem::size_of;
+
+use super::file_info::FileInfo;
 
 /// A bidirectional mapping of document names to document IDs.
 ///
@@ -8,6 +11,10 @@ use std::fmt::{Debug, Display};
 pub struct DocTable {
     pub(crate) name_to_id: HashMap<String, usize>,
     pub(crate) id_to_name: HashMap<usize, String>,
+    pub(crate) info: HashMap<usize, FileInfo>,
+    pub(crate) lengths: HashMap<usize, usize>,
+    pub(crate) total_length: usize,
+    pub(crate) next_id: usize,
 }
 
 impl DocTable {
@@ -16,29 +23,105 @@ impl DocTable {
         Self {
             name_to_id: HashMap::new(),
             id_to_name: HashMap::new(),
+            info: HashMap::new(),
+            lengths: HashMap::new(),
+            total_length: 0,
+            next_id: 0,
         }
     }
 
-    /// Adds a new document to the DocTable.
+    /// Returns the number of documents in the DocTable.
     pub fn num_docs(&self) -> usize {
         self.name_to_id.len()
     }
 
     /// Adds a new document to the DocTable.
     ///
-    /// The document is added to the DocTable with a new unique ID.
+    /// The document is added to the DocTable with a new unique ID. IDs are
+    /// never reused, even after a document is removed.
     pub fn add(&mut self, doc: &str) -> usize {
-        let id = self.name_to_id.len();
+        let id = self.next_id;
+        self.next_id += 1;
         self.name_to_id.insert(doc.to_string(), id);
         self.id_to_name.insert(id, doc.to_string());
         id
     }
 
+    /// Removes a document from the DocTable.
+    ///
+    /// Returns the ID the document had if it was in the DocTable.
+    pub fn remove(&mut self, doc: &str) -> Option<usize> {
+        let id = self.name_to_id.remove(doc)?;
+        self.id_to_name.remove(&id);
+        self.info.remove(&id);
+        if let Some(length) = self.lengths.remove(&id) {
+            self.total_length -= length;
+        }
+        Some(id)
+    }
+
+    /// Returns the names of all the documents in the DocTable.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.name_to_id.keys().map(|doc| doc.as_str())
+    }
+
+    /// Returns the IDs of all the documents in the DocTable.
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.id_to_name.keys().copied()
+    }
+
+    /// Estimates the number of bytes the DocTable holds on the heap.
+    ///
+    /// Each document name is stored twice, once in each direction.
+    pub fn memory_usage(&self) -> usize {
+        let names: usize = self.name_to_id.keys().map(|doc| doc.capacity()).sum();
+        let name_entry = size_of::<(String, usize)>() + 1;
+        let info_entry = size_of::<(usize, FileInfo)>() + 1;
+        let length_entry = size_of::<(usize, usize)>() + 1;
+        (self.name_to_id.capacity() + self.id_to_name.capacity()) * name_entry
+            + self.info.capacity() * info_entry
+            + self.lengths.capacity() * length_entry
+            + 2 * names
+    }
+
+    /// Records the file a document was parsed from.
+    pub fn set_info(&mut self, id: usize, info: FileInfo) {
+        self.info.insert(id, info);
+    }
+
+    /// Returns the file a document was parsed from, as of when it was indexed.
+    pub fn get_info(&self, id: usize) -> Option<&FileInfo> {
+        self.info.get(&id)
+    }
+
+    /// Records the number of terms indexed for a document.
+    pub fn set_length(&mut self, id: usize, length: usize) {
+        if let Some(old) = self.lengths.insert(id, length) {
+            self.total_length -= old;
+        }
+        self.total_length += length;
+    }
+
+    /// Returns the number of terms indexed for a document, or 0 if it is not
+    /// in the DocTable.
+    pub fn get_length(&self, id: usize) -> usize {
+        self.lengths.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Returns the average number of terms indexed for a document, or 0 if
+    /// the DocTable is empty.
+    pub fn avg_length(&self) -> f64 {
+        match self.lengths.len() {
+            0 => 0.0,
+            n => self.total_length as f64 / n as f64,
+        }
+    }
+
     /// Returns the ID of a document.
     ///
     /// The ID is returned if the document is in the DocTable.
     pub fn get_id(&self, doc: &str) -> Option<usize> {
-        self.name_to_id.get(doc).map(|id| *id)
+        self.name_to_id.get(doc).copied()
     }
 
     /// Returns the document name of a document ID.
@@ -49,6 +132,12 @@ impl DocTable {
     }
 }
 
+impl Default for DocTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for DocTable {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:#?}", self.name_to_id)
@@ -0,0 +1,58 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Result};
+use std::time::UNIX_EPOCH;
+
+/// A fingerprint of a file at the time it was indexed.
+///
+/// The modification time and size are cheap to read and are checked first;
+/// the content hash is only compared when one of them has changed, so that a
+/// file that was merely touched is not re-indexed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileInfo {
+    /// The modification time of the file, in nanoseconds since the epoch.
+    pub mtime: u64,
+
+    /// The size of the file in bytes.
+    pub size: u64,
+
+    /// A 64-bit FNV-1a hash of the contents of the file.
+    pub hash: u64,
+}
+
+impl FileInfo {
+    /// Reads the metadata of a file and hashes its contents.
+    ///
+    /// # Errors
+    /// * If the file cannot be opened or read, then an error is returned.
+    pub fn from_path(path: &str) -> Result<Self> {
+        let (mtime, size) = Self::stat(path)?;
+        let mut hash: u64 = FNV_OFFSET_BASIS;
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            for byte in buf[..n].iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        Ok(Self { mtime, size, hash })
+    }
+
+    /// Returns the modification time and size of a file without reading it.
+    pub fn stat(path: &str) -> Result<(u64, u64)> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok((mtime, metadata.len()))
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+
+use super::doc_index::DocIndex;
+use super::postings::PostingList;
+use super::stats::{MemoryUsage, TermStats};
+
+/// A read-only inverted index that can be searched term by term.
+///
+/// This is the abstraction the query processor searches against, so that the
+/// same queries can be served from an index built in memory (`MemIndex`) or
+/// from a persisted index file (`MmapIndex`).
+pub trait Index {
+    /// Returns the number of terms in the index.
+    fn num_terms(&self) -> usize;
+
+    /// Searches the index for a given term.
+    ///
+    /// Backends that keep their postings on the heap return them borrowed;
+    /// backends that decode them on demand return them owned.
+    fn search(&self, term: &str) -> Option<Cow<'_, PostingList>>;
+
+    /// Returns every term in the index, in no particular order.
+    fn terms(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+
+    /// Returns the terms that start with `prefix`, in sorted order.
+    ///
+    /// The terms borrow from the index, not from `prefix`, so they can be
+    /// kept after the iterator is dropped.
+    fn terms_with_prefix<'a, 'p>(
+        &'a self,
+        prefix: &'p str,
+    ) -> Box<dyn Iterator<Item = &'a str> + 'p>
+    where
+        'a: 'p;
+
+    /// Estimates the memory used by the term dictionary and postings.
+    ///
+    /// The doc table is not part of the index, so its usage is left at zero.
+    fn memory_usage(&self) -> MemoryUsage;
+
+    /// Returns how often a term occurs in the documents that are not deleted.
+    fn term_stats(&self, term: &str) -> Option<TermStats> {
+        let postings = self.search(term)?;
+        Some(TermStats::from_postings(term, &postings, self))
+    }
+
+    /// Returns whether a document has been deleted from the index.
+    ///
+    /// Postings of deleted documents may still be returned by `search` until
+    /// the index is compacted, so searches must skip them.
+    fn is_deleted(&self, _doc_id: usize) -> bool {
+        false
+    }
+}
+
+/// An inverted index that documents can be added to and deleted from.
+pub trait IndexWriter: Index {
+    /// Adds the terms of a parsed document to the index under `doc_id`.
+    fn add(&mut self, doc_index: DocIndex, doc_id: usize);
+
+    /// Deletes a document from the index.
+    ///
+    /// The document must stop matching searches immediately, but its
+    /// postings may be kept around until the index is compacted.
+    fn delete(&mut self, doc_id: usize);
+
+    /// Drops the postings of every deleted document.
+    fn compact(&mut self);
+}
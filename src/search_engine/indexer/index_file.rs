@@ -0,0 +1,662 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use crc32fast::Hasher;
+
+use super::doc_table::DocTable;
+use super::file_info::FileInfo;
+use super::index::Index;
+use super::mem_index::MemIndex;
+use super::postings::PostingList;
+use super::schema::{Schema, Tokenizer};
+
+/// The magic bytes at the start of every index file.
+pub const MAGIC: &[u8; 8] = b"ROOGLEIX";
+
+/// The version of the on-disk index format written by this build.
+///
+/// Bump this whenever the layout below changes so that older files are
+/// rejected instead of being misread.
+pub const FORMAT_VERSION: u32 = 9;
+
+/// The parts of an index file that are always read onto the heap.
+///
+/// The postings themselves are left in the file and can be decoded one term
+/// at a time with `decode_postings`.
+pub(crate) struct Layout {
+    /// The schema stored in the header.
+    pub(crate) schema: Schema,
+
+    /// The doc table stored in the file.
+    pub(crate) doc_table: DocTable,
+
+    /// Each term, the byte range of its postings in the file and their
+    /// checksum, sorted by term.
+    pub(crate) terms: Vec<(String, usize, usize, u32)>,
+
+    /// The byte range and checksum of the postings section.
+    postings: (usize, usize, u32),
+}
+
+/// A summary of an index file that passed `verify`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Verified {
+    pub schema: Schema,
+    pub num_docs: usize,
+    pub num_terms: usize,
+    pub num_postings: usize,
+    pub file_size: usize,
+}
+
+/// Writes a doc table and inverted index to the file at `path`.
+///
+/// The file is laid out as follows, where every integer is a little-endian
+/// `u64` (except the version and checksums, which are `u32`s) and every
+/// string is a length followed by its UTF-8 bytes:
+///
+/// ```text
+/// magic     "ROOGLEIX"
+/// version   u32
+/// header    section(tokenizer lowercase num_stop_words stop_word * num_stop_words)
+/// docs      section(next_id num_docs (doc_id, name, mtime, size, hash, length) * num_docs)
+/// terms     section(num_terms (term, offset, len, crc) * num_terms)
+/// postings  section((num_docs encoded_postings) * num_terms)
+/// ```
+///
+/// Every section is its length, followed by its contents, followed by the
+/// CRC-32 of its contents, so that a truncated or corrupted file is detected
+/// when it is read.
+///
+/// Postings are stored exactly as a `PostingList` encodes them in memory.
+/// The term directory is sorted by term, and each entry points at the
+/// postings of that term relative to the start of the postings section, so
+/// that a reader can look up a single term without decoding the others. Each
+/// entry also holds the CRC-32 of the postings it points at, so that they can
+/// be checked on their own when that term is read.
+///
+/// The index is written to a temporary file next to `path`, synced to disk,
+/// and only then renamed over `path`. A crash part way through a save leaves
+/// the previous index file intact.
+///
+/// # Errors
+/// * If the file cannot be created or written, then an error is returned.
+pub fn save(path: &str, schema: &Schema, doc_table: &DocTable, mem_index: &MemIndex) -> Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let result = write_file(&tmp_path, schema, doc_table, mem_index)
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+    sync_parent_dir(path)
+}
+
+fn write_file(
+    path: &str,
+    schema: &Schema,
+    doc_table: &DocTable,
+    mem_index: &MemIndex,
+) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+    let mut header = Vec::new();
+    let tokenizer = match schema.tokenizer {
+        Tokenizer::Alpha => 0,
+        Tokenizer::Whitespace => 1,
+    };
+    write_u64(&mut header, tokenizer)?;
+    write_u64(&mut header, schema.lowercase as usize)?;
+    write_u64(&mut header, schema.stop_words.len())?;
+    for word in schema.stop_words.iter() {
+        write_str(&mut header, word)?;
+    }
+    write_section(&mut w, &header)?;
+
+    let mut docs = Vec::new();
+    write_u64(&mut docs, doc_table.next_id)?;
+    write_u64(&mut docs, doc_table.id_to_name.len())?;
+    for (doc_id, name) in doc_table.id_to_name.iter() {
+        let info = doc_table.get_info(*doc_id).copied().unwrap_or_default();
+        write_u64(&mut docs, *doc_id)?;
+        write_str(&mut docs, name)?;
+        write_u64(&mut docs, info.mtime as usize)?;
+        write_u64(&mut docs, info.size as usize)?;
+        write_u64(&mut docs, info.hash as usize)?;
+        write_u64(&mut docs, doc_table.get_length(*doc_id))?;
+    }
+    write_section(&mut w, &docs)?;
+
+    // Deleted documents that have not been compacted yet are left out.
+    let mut terms: Vec<(&String, Cow<PostingList>)> = mem_index
+        .index
+        .iter()
+        .map(|(term, postings)| {
+            if mem_index.num_tombstones() == 0 {
+                return (term, Cow::Borrowed(postings));
+            }
+            let mut live = postings.clone();
+            live.retain(|doc_id| !mem_index.is_deleted(doc_id));
+            (term, Cow::Owned(live))
+        })
+        .filter(|(_, postings)| !postings.is_empty())
+        .collect();
+    terms.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut directory = Vec::new();
+    write_u64(&mut directory, terms.len())?;
+    let mut offset: usize = 0;
+    for (term, postings) in terms.iter() {
+        let len = 8 + postings.as_bytes().len();
+        let mut hasher = Hasher::new();
+        hasher.update(&(postings.len() as u64).to_le_bytes());
+        hasher.update(postings.as_bytes());
+        write_str(&mut directory, term)?;
+        write_u64(&mut directory, offset)?;
+        write_u64(&mut directory, len)?;
+        directory.write_all(&hasher.finalize().to_le_bytes())?;
+        offset += len;
+    }
+    write_section(&mut w, &directory)?;
+
+    // The postings section is streamed rather than built in memory, since it
+    // is as large as the whole index.
+    write_u64(&mut w, offset)?;
+    let mut hasher = Hasher::new();
+    for (_, postings) in terms.iter() {
+        let num_docs = (postings.len() as u64).to_le_bytes();
+        hasher.update(&num_docs);
+        hasher.update(postings.as_bytes());
+        w.write_all(&num_docs)?;
+        w.write_all(postings.as_bytes())?;
+    }
+    w.write_all(&hasher.finalize().to_le_bytes())?;
+
+    let file = w.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()
+}
+
+/// Syncs the directory holding `path`, so that a rename into it is durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &str) -> Result<()> {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &str) -> Result<()> {
+    Ok(())
+}
+
+/// Reads a doc table and inverted index from the file at `path`.
+///
+/// Every posting list in the file is decoded onto the heap, and every
+/// section's checksum is checked. Use `MmapIndex` to search an index file
+/// without loading it entirely.
+///
+/// # Errors
+/// * If the file cannot be opened or read, then an error is returned.
+/// * If the file is not an index file, was written by an incompatible
+///   version of roogle, or is truncated or corrupted, then an `InvalidData`
+///   error is returned.
+pub fn load(path: &str) -> Result<(Schema, DocTable, MemIndex)> {
+    let buf = fs::read(path)?;
+    let layout = parse_layout(&buf)?;
+    verify_postings_checksum(&buf, &layout)?;
+    let mut mem_index = MemIndex::new();
+    for (term, offset, len, crc) in layout.terms.into_iter() {
+        let postings = decode_postings(&buf[offset..offset + len], crc)
+            .map_err(|e| invalid_data(format!("corrupt postings of {:?}: {}", term, e)))?;
+        mem_index.insert_postings(term, postings);
+    }
+    Ok((layout.schema, layout.doc_table, mem_index))
+}
+
+/// Checks an index file for corruption without loading it for searching.
+///
+/// Every section's checksum is checked, every posting list is decoded, and
+/// every document a posting refers to must be in the doc table.
+///
+/// # Errors
+/// * If the file cannot be read, then an error is returned.
+/// * If the file is not a valid index file, then an `InvalidData` error
+///   describing the first problem found is returned.
+pub fn verify(path: &str) -> Result<Verified> {
+    let buf = fs::read(path)?;
+    let layout = parse_layout(&buf)?;
+    verify_postings_checksum(&buf, &layout)?;
+    let mut verified = Verified {
+        schema: layout.schema.clone(),
+        num_docs: layout.doc_table.num_docs(),
+        num_terms: layout.terms.len(),
+        num_postings: 0,
+        file_size: buf.len(),
+    };
+    for (term, offset, len, crc) in layout.terms.iter() {
+        let postings = decode_postings(&buf[*offset..offset + len], *crc)
+            .map_err(|e| invalid_data(format!("corrupt postings of {:?}: {}", term, e)))?;
+        for (doc_id, _) in postings.iter() {
+            if layout.doc_table.get_name(doc_id).is_none() {
+                return Err(invalid_data(format!(
+                    "postings of {:?} refer to unknown document {}",
+                    term, doc_id
+                )));
+            }
+        }
+        verified.num_postings += postings.len();
+    }
+    Ok(verified)
+}
+
+/// Parses the header, doc table and term directory of an index file.
+///
+/// The checksums of these sections are checked, but not the checksum of the
+/// postings section, so that a memory-mapped index does not have to read
+/// every posting to be opened; the postings of each term are checked against
+/// their own checksum by `decode_postings` instead. The byte ranges in the returned term
+/// directory are absolute offsets into `buf`, and are checked to lie within
+/// the postings section.
+pub(crate) fn parse_layout(buf: &[u8]) -> Result<Layout> {
+    if buf.len() < MAGIC.len() + 4 || &buf[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data("not a roogle index".to_string()));
+    }
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&buf[MAGIC.len()..MAGIC.len() + 4]);
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported index format version {} (expected {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    let mut pos = MAGIC.len() + 4;
+
+    let header = checked(read_section(buf, &mut pos, "header")?, "header")?;
+    let schema = parse_section("header", || {
+        let mut r = Cursor::new(header);
+        let tokenizer = match read_u64(&mut r)? {
+            0 => Tokenizer::Alpha,
+            1 => Tokenizer::Whitespace,
+            n => return Err(invalid_data(format!("unknown tokenizer {}", n))),
+        };
+        let lowercase = read_u64(&mut r)? != 0;
+        let mut stop_words = BTreeSet::new();
+        for _ in 0..read_u64(&mut r)? {
+            stop_words.insert(read_str(&mut r)?);
+        }
+        Ok(Schema {
+            tokenizer,
+            lowercase,
+            stop_words,
+        })
+    })?;
+
+    let docs = checked(read_section(buf, &mut pos, "doc table")?, "doc table")?;
+    let doc_table = parse_section("doc table", || {
+        let mut r = Cursor::new(docs);
+        let mut doc_table = DocTable::new();
+        doc_table.next_id = read_u64(&mut r)?;
+        for _ in 0..read_u64(&mut r)? {
+            let doc_id = read_u64(&mut r)?;
+            let name = read_str(&mut r)?;
+            let info = FileInfo {
+                mtime: read_u64(&mut r)? as u64,
+                size: read_u64(&mut r)? as u64,
+                hash: read_u64(&mut r)? as u64,
+            };
+            doc_table.name_to_id.insert(name.clone(), doc_id);
+            doc_table.id_to_name.insert(doc_id, name);
+            doc_table.set_info(doc_id, info);
+            doc_table.set_length(doc_id, read_u64(&mut r)?);
+        }
+        Ok(doc_table)
+    })?;
+
+    let directory = checked(
+        read_section(buf, &mut pos, "term directory")?,
+        "term directory",
+    )?;
+    let directory = parse_section("term directory", || {
+        let mut r = Cursor::new(directory);
+        let num_terms = read_u64(&mut r)?;
+        let mut entries = Vec::with_capacity(num_terms.min(buf.len()));
+        for _ in 0..num_terms {
+            let term = read_str(&mut r)?;
+            let offset = read_u64(&mut r)?;
+            let len = read_u64(&mut r)?;
+            let mut crc = [0u8; 4];
+            r.read_exact(&mut crc)?;
+            entries.push((term, offset, len, u32::from_le_bytes(crc)));
+        }
+        Ok(entries)
+    })?;
+
+    let postings_start = pos + 8;
+    let (postings, crc) = read_section(buf, &mut pos, "postings")?;
+    if pos != buf.len() {
+        return Err(invalid_data(format!(
+            "{} trailing bytes after the postings",
+            buf.len() - pos
+        )));
+    }
+
+    let mut terms: Vec<(String, usize, usize, u32)> = Vec::with_capacity(directory.len());
+    for (term, offset, len, crc) in directory.into_iter() {
+        if offset.saturating_add(len) > postings.len() {
+            return Err(invalid_data(format!(
+                "postings of {:?} out of bounds",
+                term
+            )));
+        }
+        if let Some((prev, _, _, _)) = terms.last() {
+            if *prev >= term {
+                return Err(invalid_data(format!(
+                    "term directory unsorted at {:?}",
+                    term
+                )));
+            }
+        }
+        terms.push((term, postings_start + offset, len, crc));
+    }
+    Ok(Layout {
+        schema,
+        doc_table,
+        terms,
+        postings: (postings_start, postings.len(), crc),
+    })
+}
+
+/// Checks the checksum of the postings section, which `parse_layout` skips.
+fn verify_postings_checksum(buf: &[u8], layout: &Layout) -> Result<()> {
+    let (start, len, crc) = layout.postings;
+    checked((&buf[start..start + len], crc), "postings").map(|_| ())
+}
+
+/// Decodes the postings of a single term, after checking them against the
+/// checksum stored for them in the term directory.
+///
+/// # Errors
+/// * If the postings do not match their checksum or cannot be decoded, then
+///   an `InvalidData` error is returned.
+pub(crate) fn decode_postings(buf: &[u8], crc: u32) -> Result<PostingList> {
+    checked((buf, crc), "postings")?;
+    let mut r = Cursor::new(buf);
+    let num_docs = read_u64(&mut r)?;
+    PostingList::from_bytes(buf[8..].to_vec(), num_docs)
+}
+
+/// Reads the section starting at `pos` and moves `pos` past it.
+///
+/// Returns the contents of the section and their stored checksum.
+fn read_section<'a>(buf: &'a [u8], pos: &mut usize, name: &str) -> Result<(&'a [u8], u32)> {
+    let truncated = || invalid_data(format!("index file is truncated in the {}", name));
+    let mut r = Cursor::new(buf.get(*pos..).ok_or_else(truncated)?);
+    let len = read_u64(&mut r).map_err(|_| truncated())?;
+    let start = *pos + 8;
+    let end = start
+        .checked_add(len)
+        .filter(|end| end.saturating_add(4) <= buf.len())
+        .ok_or_else(truncated)?;
+    let mut crc = [0u8; 4];
+    crc.copy_from_slice(&buf[end..end + 4]);
+    *pos = end + 4;
+    Ok((&buf[start..end], u32::from_le_bytes(crc)))
+}
+
+/// Returns the contents of a section if they match their checksum.
+fn checked<'a>((contents, crc): (&'a [u8], u32), name: &str) -> Result<&'a [u8]> {
+    if crc32fast::hash(contents) != crc {
+        return Err(invalid_data(format!("checksum mismatch in the {}", name)));
+    }
+    Ok(contents)
+}
+
+/// Parses the contents of a section, describing any error that occurs.
+fn parse_section<T, F: FnOnce() -> Result<T>>(name: &str, parse: F) -> Result<T> {
+    parse().map_err(|e| invalid_data(format!("corrupt {}: {}", name, e)))
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn write_section<W: Write>(w: &mut W, contents: &[u8]) -> Result<()> {
+    write_u64(w, contents.len())?;
+    w.write_all(contents)?;
+    w.write_all(&crc32fast::hash(contents).to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, n: usize) -> Result<()> {
+    w.write_all(&(n as u64).to_le_bytes())
+}
+
+fn write_str<W: Write>(w: &mut W, s: &str) -> Result<()> {
+    write_u64(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<usize> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf) as usize)
+}
+
+fn read_str<R: Read>(r: &mut R) -> Result<String> {
+    let len = read_u64(r)?;
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated string"));
+    }
+    String::from_utf8(buf).map_err(|e| invalid_data(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::indexer::doc_index::DocIndex;
+    use crate::search_engine::indexer::index::IndexWriter;
+    use crate::search_engine::indexer::mmap_index::MmapIndex;
+    use std::panic::{self, AssertUnwindSafe};
+
+    const DOCS: [(&str, &str); 3] = [
+        ("a.txt", "steve the hair"),
+        ("b.txt", "hair hair of steve"),
+        ("c.txt", "long hair"),
+    ];
+
+    /// Builds an index of `DOCS` and saves it to a file named after `test`.
+    fn save_docs(test: &str) -> (String, DocTable, MemIndex) {
+        let schema = Schema::default();
+        let mut doc_table = DocTable::new();
+        let mut mem_index = MemIndex::new();
+        for (name, text) in DOCS.iter() {
+            let doc_index = DocIndex::from_text(name, text, &schema);
+            let doc_id = doc_table.add(name);
+            doc_table.set_length(doc_id, doc_index.length());
+            mem_index.add(doc_index, doc_id);
+        }
+        let path = std::env::temp_dir().join(format!("roogle-{}-{}.idx", test, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        save(&path, &schema, &doc_table, &mem_index).unwrap();
+        (path, doc_table, mem_index)
+    }
+
+    /// Writes `buf` over the file at `path` and returns the error loading it.
+    fn load_err(path: &str, buf: &[u8]) -> Error {
+        fs::write(path, buf).unwrap();
+        let err = match load(path) {
+            Ok(_) => panic!("loaded a corrupt index"),
+            Err(err) => err,
+        };
+        assert_eq!(ErrorKind::InvalidData, err.kind(), "{}", err);
+        assert!(verify(path).is_err());
+        err
+    }
+
+    #[test]
+    fn round_trip() {
+        let (path, doc_table, mem_index) = save_docs("round-trip");
+        let (schema, loaded_table, loaded_index) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Schema::default(), schema);
+        assert_eq!(doc_table.next_id, loaded_table.next_id);
+        assert_eq!(doc_table.id_to_name, loaded_table.id_to_name);
+        assert_eq!(doc_table.lengths, loaded_table.lengths);
+        assert_eq!(mem_index.index, loaded_index.index);
+        assert_eq!(mem_index.terms, loaded_index.terms);
+    }
+
+    #[test]
+    fn deleted_documents_are_not_saved() {
+        let (path, mut doc_table, mut mem_index) = save_docs("deleted");
+        let doc_id = doc_table.remove("c.txt").unwrap();
+        mem_index.delete(doc_id);
+        save(&path, &Schema::default(), &doc_table, &mem_index).unwrap();
+        let (_, _, loaded_index) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded_index.search("long").is_none());
+        let hair: Vec<usize> = loaded_index
+            .search("hair")
+            .unwrap()
+            .iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert!(!hair.contains(&doc_id));
+        assert_eq!(2, hair.len());
+    }
+
+    #[test]
+    fn truncated() {
+        let (path, _, _) = save_docs("truncated");
+        let buf = fs::read(&path).unwrap();
+        for len in [
+            0,
+            4,
+            MAGIC.len() + 4,
+            20,
+            buf.len() / 2,
+            buf.len() - 5,
+            buf.len() - 1,
+        ] {
+            load_err(&path, &buf[..len]);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let (path, _, _) = save_docs("checksum");
+        let buf = fs::read(&path).unwrap();
+        let find = |needle: &[u8]| buf.windows(needle.len()).position(|w| w == needle).unwrap();
+        // The tokenizer, a document name, a term in the term directory, and
+        // the last byte of the postings, just before the checksum of the
+        // section.
+        for (pos, section) in [
+            (MAGIC.len() + 4 + 8, "header"),
+            (find(b"b.txt"), "doc table"),
+            (find(b"steve"), "term directory"),
+            (buf.len() - 5, "postings"),
+        ] {
+            let mut corrupt = buf.clone();
+            corrupt[pos] ^= 1;
+            let err = load_err(&path, &corrupt);
+            assert_eq!(
+                format!("checksum mismatch in the {}", section),
+                err.to_string()
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrong_version() {
+        let (path, _, _) = save_docs("version");
+        let mut buf = fs::read(&path).unwrap();
+        buf[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let err = load_err(&path, &buf);
+        assert!(err
+            .to_string()
+            .starts_with("unsupported index format version"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn verify_counts() {
+        let (path, _, _) = save_docs("verify");
+        let verified = verify(&path).unwrap();
+        let file_size = fs::metadata(&path).unwrap().len() as usize;
+        fs::remove_file(&path).unwrap();
+
+        // hair, long, of, steve and the, in 3 + 1 + 1 + 2 + 1 documents.
+        let expected = Verified {
+            schema: Schema::default(),
+            num_docs: 3,
+            num_terms: 5,
+            num_postings: 8,
+            file_size,
+        };
+        assert_eq!(expected, verified);
+    }
+
+    /// Searches for a term that is expected to panic, and returns the panic
+    /// message.
+    fn search_panic(index: &MmapIndex, term: &str) -> String {
+        let err = panic::catch_unwind(AssertUnwindSafe(|| index.search(term)))
+            .expect_err("searched corrupt postings");
+        err.downcast::<String>().map(|msg| *msg).unwrap()
+    }
+
+    #[test]
+    fn mmap_checks_postings_of_each_term() {
+        let (path, _, mem_index) = save_docs("mmap");
+        // The postings of the last term, "the", end just before the checksum
+        // of the postings section.
+        let mut buf = fs::read(&path).unwrap();
+        let last = buf.len() - 5;
+        buf[last] ^= 1;
+        fs::write(&path, &buf).unwrap();
+        let (_, _, mmap_index) = MmapIndex::open(&path).unwrap();
+
+        assert_eq!(
+            "corrupt postings of \"the\": checksum mismatch in the postings",
+            search_panic(&mmap_index, "the")
+        );
+        for term in ["hair", "long", "of", "steve"] {
+            assert_eq!(mem_index.search(term), mmap_index.search(term), "{}", term);
+        }
+        drop(mmap_index);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn postings_of_unknown_documents() {
+        // Saving c.txt's postings without c.txt leaves every checksum valid.
+        let (path, mut doc_table, mem_index) = save_docs("unknown");
+        doc_table.remove("c.txt").unwrap();
+        save(&path, &Schema::default(), &doc_table, &mem_index).unwrap();
+
+        let err = verify(&path).expect_err("verified postings of an unknown document");
+        assert_eq!(
+            "postings of \"hair\" refer to unknown document 2",
+            err.to_string()
+        );
+        let (_, _, mmap_index) = MmapIndex::open(&path).unwrap();
+        assert_eq!(
+            "postings of \"long\" refer to unknown document 2",
+            search_panic(&mmap_index, "long")
+        );
+        assert!(mmap_index.search("steve").is_some());
+        drop(mmap_index);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,15 +1,29 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Display};
+use std::mem::size_of;
+use std::ops::Bound;
 
 use super::doc_index::DocIndex;
+use super::index::{Index, IndexWriter};
+use super::postings::PostingList;
+use super::stats::MemoryUsage;
 
 /// An in-memory inverted index.
 ///
 /// A MemIndex is a combination of many smaller document indexes so that a
 /// search can be performed across multiple documents. The MemIndex is a
-/// map from a term to a map of document IDs to positions in the document.
+/// map from a term to a compressed list of the documents it occurs in and
+/// its positions in each document (see `PostingList`). The terms are also
+/// kept in order, so that every term with a given prefix can be found.
+///
+/// Deleted documents are tombstoned rather than removed straight away:
+/// searches skip them immediately, and `compact` later drops their postings.
 pub struct MemIndex {
-    pub(crate) index: HashMap<String, HashMap<usize, Vec<usize>>>,
+    pub(crate) index: HashMap<String, PostingList>,
+    pub(crate) terms: BTreeSet<String>,
+    pub(crate) tombstones: HashSet<usize>,
 }
 
 impl MemIndex {
@@ -17,27 +31,113 @@ impl MemIndex {
     pub fn new() -> Self {
         Self {
             index: HashMap::new(),
+            terms: BTreeSet::new(),
+            tombstones: HashSet::new(),
         }
     }
 
-    /// Returns the number of terms in the index.
-    pub fn num_terms(&self) -> usize {
+    /// Adds the postings of a term that is not in the index yet.
+    pub(crate) fn insert_postings(&mut self, term: String, postings: PostingList) {
+        self.terms.insert(term.clone());
+        self.index.insert(term, postings);
+    }
+
+    /// Returns the number of deleted documents that have not been compacted.
+    pub fn num_tombstones(&self) -> usize {
+        self.tombstones.len()
+    }
+}
+
+impl Index for MemIndex {
+    fn num_terms(&self) -> usize {
         self.index.len()
     }
 
-    /// Adds a new document to the MemIndex.
-    pub fn add(&mut self, mut doc_index: DocIndex, doc_id: usize) {
+    fn search(&self, term: &str) -> Option<Cow<'_, PostingList>> {
+        self.index.get(term).map(Cow::Borrowed)
+    }
+
+    fn terms(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.index.keys().map(|term| term.as_str()))
+    }
+
+    fn terms_with_prefix<'a, 'p>(
+        &'a self,
+        prefix: &'p str,
+    ) -> Box<dyn Iterator<Item = &'a str> + 'p>
+    where
+        'a: 'p,
+    {
+        let range = (Bound::Included(prefix), Bound::Unbounded);
+        Box::new(
+            self.terms
+                .range::<str, _>(range)
+                .map(|term| term.as_str())
+                .take_while(move |term| term.starts_with(prefix)),
+        )
+    }
+
+    /// The table overhead is estimated from its capacity, so the estimate is
+    /// a lower bound of the real usage.
+    fn memory_usage(&self) -> MemoryUsage {
+        let entry = size_of::<(String, PostingList)>() + 1;
+        // Every term is stored twice: once in the table, once in order.
+        let terms: usize = self.index.keys().map(|term| term.capacity()).sum();
+        let sorted = self.terms.len() * size_of::<String>() + terms;
+        let tombstones = self.tombstones.capacity() * (size_of::<usize>() + 1);
+        MemoryUsage {
+            dictionary: self.index.capacity() * entry + terms + sorted + tombstones,
+            postings: self.index.values().map(|p| p.heap_size()).sum(),
+            ..MemoryUsage::default()
+        }
+    }
+
+    fn is_deleted(&self, doc_id: usize) -> bool {
+        self.tombstones.contains(&doc_id)
+    }
+}
+
+impl Default for MemIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndexWriter for MemIndex {
+    fn add(&mut self, mut doc_index: DocIndex, doc_id: usize) {
         for (term, positions) in doc_index.index.drain() {
-            self.index
-                .entry(term)
-                .or_insert(HashMap::new())
-                .insert(doc_id, positions);
+            let postings = match self.index.entry(term) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    self.terms.insert(entry.key().clone());
+                    entry.insert(PostingList::new())
+                }
+            };
+            postings.insert(doc_id, &positions);
         }
     }
 
-    /// Searches the MemIndex for a given term.
-    pub fn search(&self, term: &str) -> Option<&HashMap<usize, Vec<usize>>> {
-        self.index.get(term)
+    /// Tombstones the document: it is excluded from searches right away, but
+    /// its postings stay in the index until `compact` is called.
+    fn delete(&mut self, doc_id: usize) {
+        self.tombstones.insert(doc_id);
+    }
+
+    /// Terms that no longer occur in any document are dropped as well.
+    fn compact(&mut self) {
+        if self.tombstones.is_empty() {
+            return;
+        }
+        let tombstones = &self.tombstones;
+        let terms = &mut self.terms;
+        self.index.retain(|term, postings| {
+            postings.retain(|doc_id| !tombstones.contains(&doc_id));
+            if postings.is_empty() {
+                terms.remove(term);
+            }
+            !postings.is_empty()
+        });
+        self.tombstones.clear();
     }
 }
 
@@ -0,0 +1,145 @@
+use std::borrow::Cow;
+use std::fmt::{Debug, Display};
+use std::fs::File;
+use std::io::Result;
+use std::mem::size_of;
+
+use memmap2::Mmap;
+
+use super::doc_table::DocTable;
+use super::index::Index;
+use super::index_file;
+use super::postings::PostingList;
+use super::schema::Schema;
+use super::stats::MemoryUsage;
+
+/// A read-only inverted index backed by a memory-mapped index file.
+///
+/// Only the term directory and the IDs of the documents are kept on the heap,
+/// sorted. The compressed postings of a term stay in the mapped file and are
+/// only copied out when that term is searched for, so the operating system
+/// can page them in and out as needed.
+pub struct MmapIndex {
+    mmap: Mmap,
+    terms: Vec<(String, usize, usize, u32)>,
+    doc_ids: Vec<usize>,
+}
+
+impl MmapIndex {
+    /// Memory-maps the index file at `path`.
+    ///
+    /// Returns the schema and doc table stored in the file alongside the
+    /// index. Only the checksums of the header, doc table and term directory
+    /// are checked when the file is opened. The postings of a term are checked
+    /// against their own checksum each time the term is searched for, which
+    /// panics if they are corrupt, or can all be checked up front with
+    /// `index_file::verify`.
+    ///
+    /// # Errors
+    /// * If the file cannot be opened or mapped, then an error is returned.
+    /// * If the file is not a valid index file, then an error is returned.
+    pub fn open(path: &str) -> Result<(Schema, DocTable, Self)> {
+        let file = File::open(path)?;
+        // SAFETY: the index is only ever read through this mapping. Index
+        // files are written once by `index_file::save`, which replaces them
+        // by renaming rather than modifying them in place.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let layout = index_file::parse_layout(&mmap)?;
+        let mut doc_ids: Vec<usize> = layout.doc_table.ids().collect();
+        doc_ids.sort_unstable();
+        let index = Self {
+            mmap,
+            terms: layout.terms,
+            doc_ids,
+        };
+        Ok((layout.schema, layout.doc_table, index))
+    }
+}
+
+impl Index for MmapIndex {
+    fn num_terms(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// # Panics
+    /// * If the postings of the term do not match their checksum, or refer to
+    ///   a document that is not in the doc table, as the index file is
+    ///   corrupt.
+    fn search(&self, term: &str) -> Option<Cow<'_, PostingList>> {
+        let i = self
+            .terms
+            .binary_search_by(|(t, _, _, _)| t.as_str().cmp(term))
+            .ok()?;
+        let (_, offset, len, crc) = self.terms[i];
+        let postings = index_file::decode_postings(&self.mmap[offset..offset + len], crc)
+            .unwrap_or_else(|e| panic!("corrupt postings of {:?}: {}", term, e));
+        let unknown = postings
+            .iter()
+            .map(|(doc_id, _)| doc_id)
+            .find(|doc_id| self.doc_ids.binary_search(doc_id).is_err());
+        if let Some(doc_id) = unknown {
+            panic!(
+                "postings of {:?} refer to unknown document {}",
+                term, doc_id
+            );
+        }
+        Some(Cow::Owned(postings))
+    }
+
+    fn terms(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.terms.iter().map(|(term, _, _, _)| term.as_str()))
+    }
+
+    fn terms_with_prefix<'a, 'p>(
+        &'a self,
+        prefix: &'p str,
+    ) -> Box<dyn Iterator<Item = &'a str> + 'p>
+    where
+        'a: 'p,
+    {
+        let start = self
+            .terms
+            .partition_point(|(t, _, _, _)| t.as_str() < prefix);
+        Box::new(
+            self.terms[start..]
+                .iter()
+                .map(|(term, _, _, _)| term.as_str())
+                .take_while(move |term| term.starts_with(prefix)),
+        )
+    }
+
+    /// Only the term directory and document IDs are on the heap; the postings
+    /// are counted as mapped, whether or not they are currently paged in.
+    fn memory_usage(&self) -> MemoryUsage {
+        let entry = size_of::<(String, usize, usize, u32)>();
+        let terms: usize = self
+            .terms
+            .iter()
+            .map(|(term, _, _, _)| term.capacity())
+            .sum();
+        MemoryUsage {
+            dictionary: self.terms.capacity() * entry
+                + terms
+                + self.doc_ids.capacity() * size_of::<usize>(),
+            mapped: self.mmap.len(),
+            ..MemoryUsage::default()
+        }
+    }
+}
+
+impl Display for MmapIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MmapIndex({} terms, {} bytes)",
+            self.terms.len(),
+            self.mmap.len()
+        )
+    }
+}
+
+impl Debug for MmapIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.terms)
+    }
+}
@@ -1,3 +1,11 @@
 pub mod doc_index;
 pub mod doc_table;
+pub mod file_info;
+pub mod index;
+pub mod index_file;
 pub mod mem_index;
+pub mod mmap_index;
+pub mod postings;
+pub mod schema;
+pub mod segmented_index;
+pub mod stats;
@@ -0,0 +1,445 @@
+use std::fmt::{Debug, Display};
+use std::io::{Error, ErrorKind, Result};
+use std::iter::FromIterator;
+
+/// A compressed list of the documents a term occurs in, and where.
+///
+/// Postings are kept sorted by document ID. Each posting is stored as the gap
+/// from the previous document ID, the number of positions, the number of
+/// bytes the positions take up, and then the gaps between the (sorted)
+/// positions. Every number is written as a variable-length integer (LEB128),
+/// so small gaps take a single byte:
+///
+/// ```text
+/// (doc_gap num_positions positions_len position_gap*)*
+/// ```
+///
+/// Nothing is decoded up front: iterating over the list decodes one posting
+/// at a time, and the positions of a posting are only decoded if they are
+/// read. Thanks to `positions_len`, a posting can be skipped over without
+/// decoding its positions at all.
+///
+/// Every `SKIP_INTERVAL` postings, a skip pointer records where the next
+/// posting starts, so that `PostingsIter::seek` can jump close to a document
+/// with a binary search instead of walking every posting before it. Skip
+/// pointers are not part of the encoding; they are rebuilt whenever a list
+/// is decoded with `from_bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::indexer::postings::PostingList;
+///
+/// let mut postings = PostingList::new();
+/// postings.insert(3, &[0, 6]);
+/// postings.insert(1, &[300]);
+///
+/// let docs: Vec<(usize, Vec<usize>)> = postings.iter().map(|(d, p)| (d, p.collect())).collect();
+/// assert_eq!(docs, vec![(1, vec![300]), (3, vec![0, 6])]);
+/// assert!(postings.get(3).unwrap().contains(6));
+/// assert!(postings.get(2).is_none());
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct PostingList {
+    data: Vec<u8>,
+    num_docs: usize,
+    last_doc_id: usize,
+    skips: Vec<Skip>,
+}
+
+/// The number of postings between two skip pointers.
+pub const SKIP_INTERVAL: usize = 64;
+
+/// A pointer to the start of a posting in an encoded PostingList.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Skip {
+    /// The ID of the document just before the posting.
+    prev_doc_id: usize,
+
+    /// The byte offset of the posting.
+    offset: usize,
+
+    /// The number of postings before the posting.
+    index: usize,
+}
+
+impl PostingList {
+    /// Creates a new empty PostingList.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps postings that were encoded by another PostingList.
+    ///
+    /// # Errors
+    /// * If `data` does not hold exactly `num_docs` well-formed postings,
+    ///   then an `InvalidData` error is returned.
+    pub fn from_bytes(data: Vec<u8>, num_docs: usize) -> Result<Self> {
+        let mut list = Self {
+            data,
+            num_docs,
+            last_doc_id: 0,
+            skips: Vec::new(),
+        };
+        let mut iter = list.iter();
+        let mut skips = Vec::with_capacity(num_docs / SKIP_INTERVAL);
+        for index in 0..num_docs {
+            if index > 0 && index.is_multiple_of(SKIP_INTERVAL) {
+                skips.push(Skip {
+                    prev_doc_id: iter.prev_doc_id,
+                    offset: iter.offset,
+                    index,
+                });
+            }
+            let prev_doc_id = iter.prev_doc_id;
+            match iter.next() {
+                Some((doc_id, _)) if index == 0 || doc_id > prev_doc_id => {}
+                Some(_) => return Err(Error::new(ErrorKind::InvalidData, "unsorted postings")),
+                None => return Err(Error::new(ErrorKind::InvalidData, "truncated postings")),
+            }
+        }
+        if iter.offset != list.data.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "trailing bytes after postings",
+            ));
+        }
+        list.last_doc_id = iter.prev_doc_id;
+        list.skips = skips;
+        Ok(list)
+    }
+
+    /// Returns the encoded postings.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of documents in the list.
+    pub fn len(&self) -> usize {
+        self.num_docs
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.num_docs == 0
+    }
+
+    /// Returns the number of bytes the list holds on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.data.capacity() + self.skips.capacity() * std::mem::size_of::<Skip>()
+    }
+
+    /// Adds the positions of a term in a document to the list.
+    ///
+    /// Appending a document with a larger ID than every other document in
+    /// the list is cheap. Otherwise the list is decoded and re-encoded, and
+    /// any positions already stored for that document are replaced.
+    pub fn insert(&mut self, doc_id: usize, positions: &[usize]) {
+        if self.num_docs == 0 || doc_id > self.last_doc_id {
+            self.push(doc_id - self.last_doc_id, doc_id, positions);
+            return;
+        }
+        let mut postings: Vec<(usize, Vec<usize>)> = self
+            .iter()
+            .filter(|(d, _)| *d != doc_id)
+            .map(|(d, p)| (d, p.collect()))
+            .collect();
+        postings.push((doc_id, positions.to_vec()));
+        *self = postings.into_iter().collect();
+    }
+
+    /// Returns the positions of the term in a document, if it occurs there.
+    pub fn get(&self, doc_id: usize) -> Option<Positions<'_>> {
+        match self.iter().seek(doc_id) {
+            Some((d, positions)) if d == doc_id => Some(positions),
+            _ => None,
+        }
+    }
+
+    /// Keeps only the postings of the documents for which `f` returns true.
+    pub fn retain<F: FnMut(usize) -> bool>(&mut self, mut f: F) {
+        let mut retained = Self::new();
+        for (doc_id, positions) in self.iter() {
+            if f(doc_id) {
+                let positions: Vec<usize> = positions.collect();
+                retained.push(doc_id - retained.last_doc_id, doc_id, &positions);
+            }
+        }
+        *self = retained;
+    }
+
+    /// Merges several lists into one.
+    ///
+    /// If a document is in more than one list, then the positions from the
+    /// last of those lists are kept.
+    pub fn merge<'a, T: IntoIterator<Item = &'a PostingList>>(lists: T) -> Self {
+        let mut postings: Vec<(usize, Vec<usize>)> = Vec::new();
+        for list in lists {
+            postings.extend(list.iter().map(|(d, p)| (d, p.collect())));
+        }
+        postings.reverse();
+        postings.sort_by_key(|(doc_id, _)| *doc_id);
+        postings.dedup_by_key(|(doc_id, _)| *doc_id);
+        postings.into_iter().collect()
+    }
+
+    /// Returns an iterator over the documents in the list, in ascending order
+    /// of document ID.
+    pub fn iter(&self) -> PostingsIter<'_> {
+        PostingsIter {
+            data: &self.data,
+            skips: &self.skips,
+            offset: 0,
+            index: 0,
+            len: self.num_docs,
+            prev_doc_id: 0,
+        }
+    }
+
+    /// Returns a cursor positioned on the first document in the list.
+    pub fn cursor(&self) -> Cursor<'_> {
+        let mut iter = self.iter();
+        let current = iter.next();
+        Cursor { iter, current }
+    }
+
+    /// Appends a posting whose document ID is `gap` more than the last one.
+    fn push(&mut self, gap: usize, doc_id: usize, positions: &[usize]) {
+        let mut sorted = positions.to_vec();
+        sorted.sort_unstable();
+        let mut encoded = Vec::with_capacity(sorted.len());
+        let mut prev = 0;
+        for pos in sorted.iter() {
+            write_varint(&mut encoded, pos - prev);
+            prev = *pos;
+        }
+        if self.num_docs > 0 && self.num_docs.is_multiple_of(SKIP_INTERVAL) {
+            self.skips.push(Skip {
+                prev_doc_id: self.last_doc_id,
+                offset: self.data.len(),
+                index: self.num_docs,
+            });
+        }
+        write_varint(&mut self.data, gap);
+        write_varint(&mut self.data, sorted.len());
+        write_varint(&mut self.data, encoded.len());
+        self.data.extend_from_slice(&encoded);
+        self.num_docs += 1;
+        self.last_doc_id = doc_id;
+    }
+}
+
+impl FromIterator<(usize, Vec<usize>)> for PostingList {
+    /// Builds a PostingList from unordered postings.
+    fn from_iter<T: IntoIterator<Item = (usize, Vec<usize>)>>(iter: T) -> Self {
+        let mut postings: Vec<(usize, Vec<usize>)> = iter.into_iter().collect();
+        postings.sort_unstable_by_key(|(doc_id, _)| *doc_id);
+        let mut list = Self::new();
+        for (doc_id, positions) in postings.iter() {
+            list.push(doc_id - list.last_doc_id, *doc_id, positions);
+        }
+        list
+    }
+}
+
+/// An iterator over the postings of a PostingList.
+#[derive(Clone)]
+pub struct PostingsIter<'a> {
+    data: &'a [u8],
+    skips: &'a [Skip],
+    offset: usize,
+    index: usize,
+    len: usize,
+    prev_doc_id: usize,
+}
+
+impl<'a> PostingsIter<'a> {
+    /// Skips forward to the first posting of a document whose ID is at least
+    /// `doc_id`, and returns it.
+    ///
+    /// Skip pointers are used to jump over whole blocks of postings that lie
+    /// before `doc_id`; the postings in between are read but their positions
+    /// are not decoded.
+    pub fn seek(&mut self, doc_id: usize) -> Option<(usize, Positions<'a>)> {
+        let i = self.skips.partition_point(|skip| skip.prev_doc_id < doc_id);
+        if let Some(skip) = i.checked_sub(1).map(|i| self.skips[i]) {
+            if skip.index > self.index {
+                self.offset = skip.offset;
+                self.index = skip.index;
+                self.prev_doc_id = skip.prev_doc_id;
+            }
+        }
+        self.find(|(d, _)| *d >= doc_id)
+    }
+}
+
+impl<'a> Iterator for PostingsIter<'a> {
+    type Item = (usize, Positions<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.len {
+            return None;
+        }
+        let gap = read_varint(self.data, &mut self.offset)?;
+        let num_positions = read_varint(self.data, &mut self.offset)?;
+        let len = read_varint(self.data, &mut self.offset)?;
+        let start = self.offset;
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())?;
+        self.offset = end;
+        self.index += 1;
+        self.prev_doc_id = self.prev_doc_id.checked_add(gap)?;
+        let positions = Positions {
+            data: &self.data[start..end],
+            offset: 0,
+            remaining: num_positions,
+            prev: 0,
+        };
+        Some((self.prev_doc_id, positions))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len - self.index))
+    }
+}
+
+/// A cursor that only ever moves forward through a posting list.
+///
+/// Unlike a `PostingsIter`, a cursor remembers the posting it is on, which
+/// makes it convenient for walking several posting lists in lockstep.
+#[derive(Clone)]
+pub struct Cursor<'a> {
+    iter: PostingsIter<'a>,
+    current: Option<(usize, Positions<'a>)>,
+}
+
+impl<'a> Cursor<'a> {
+    /// Returns the ID of the document the cursor is on, or `None` once the
+    /// cursor has moved past the end of the list.
+    pub fn doc_id(&self) -> Option<usize> {
+        self.current.as_ref().map(|(doc_id, _)| *doc_id)
+    }
+
+    /// Returns the positions in the document the cursor is on.
+    pub fn positions(&self) -> Option<Positions<'a>> {
+        self.current
+            .as_ref()
+            .map(|(_, positions)| positions.clone())
+    }
+
+    /// Moves the cursor to the next document in the list.
+    pub fn advance(&mut self) -> Option<usize> {
+        self.current = self.iter.next();
+        self.doc_id()
+    }
+
+    /// Moves the cursor to the first document whose ID is at least `doc_id`.
+    ///
+    /// If the cursor is already there or beyond, then it does not move.
+    pub fn seek(&mut self, doc_id: usize) -> Option<usize> {
+        match self.doc_id() {
+            Some(current) if current < doc_id => {
+                self.current = self.iter.seek(doc_id);
+                self.doc_id()
+            }
+            current => current,
+        }
+    }
+
+    /// Moves the cursor to `doc_id` and returns its positions there, if the
+    /// term occurs in that document.
+    pub fn positions_in(&mut self, doc_id: usize) -> Option<Positions<'a>> {
+        match self.seek(doc_id) {
+            Some(current) if current == doc_id => self.positions(),
+            _ => None,
+        }
+    }
+}
+
+/// A lazily decoded, ascending list of the positions of a term in a document.
+#[derive(Clone)]
+pub struct Positions<'a> {
+    data: &'a [u8],
+    offset: usize,
+    remaining: usize,
+    prev: usize,
+}
+
+impl<'a> Iterator for Positions<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let gap = read_varint(self.data, &mut self.offset)?;
+        self.remaining -= 1;
+        self.prev = self.prev.checked_add(gap)?;
+        Some(self.prev)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Positions<'a> {}
+
+impl<'a> Positions<'a> {
+    /// Returns whether `pos` is one of the positions.
+    ///
+    /// Since the positions are sorted, decoding stops as soon as a larger
+    /// position is found.
+    pub fn contains(self, pos: usize) -> bool {
+        for p in self {
+            if p >= pos {
+                return p == pos;
+            }
+        }
+        false
+    }
+}
+
+/// Appends `n` to `buf` as an unsigned LEB128 integer.
+fn write_varint(buf: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Reads an unsigned LEB128 integer from `buf` at `offset`.
+///
+/// Returns `None` if the integer is truncated or does not fit in a `usize`.
+fn read_varint(buf: &[u8], offset: &mut usize) -> Option<usize> {
+    let mut n: usize = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *buf.get(*offset)?;
+        *offset += 1;
+        if shift >= usize::BITS {
+            return None;
+        }
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+        shift += 7;
+    }
+}
+
+impl Display for PostingList {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(d, p)| (d, p.collect::<Vec<_>>())))
+            .finish()
+    }
+}
+
+impl Debug for PostingList {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+use super::doc_index;
+use crate::search_engine::filters::stop_words::STOP_WORDS;
+
+/// How text is split into terms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tokenizer {
+    /// A term is a run of ASCII letters; everything else separates terms.
+    #[default]
+    Alpha,
+
+    /// A term is a run of non-whitespace characters, with leading and
+    /// trailing punctuation removed.
+    Whitespace,
+}
+
+impl FromStr for Tokenizer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alpha" => Ok(Tokenizer::Alpha),
+            "whitespace" => Ok(Tokenizer::Whitespace),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown tokenizer {:?} (expected alpha or whitespace)", s),
+            )),
+        }
+    }
+}
+
+impl Display for Tokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Tokenizer::Alpha => write!(f, "alpha"),
+            Tokenizer::Whitespace => write!(f, "whitespace"),
+        }
+    }
+}
+
+/// How documents and queries are analyzed into terms.
+///
+/// An index is only searchable with the schema it was built with: a query
+/// analyzed differently from the documents produces terms that were never
+/// indexed. The schema is therefore stored in the index file, and the query
+/// processor always analyzes queries with the schema of its index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schema {
+    /// How text is split into terms.
+    pub tokenizer: Tokenizer,
+
+    /// Whether terms are lowercased.
+    pub lowercase: bool,
+
+    /// The terms that are left out of the index. Empty to index every term.
+    pub stop_words: BTreeSet<String>,
+}
+
+impl Schema {
+    /// Returns this schema with the built-in English stop words left out of
+    /// the index.
+    pub fn with_stop_words(mut self) -> Self {
+        self.stop_words = STOP_WORDS.iter().map(|word| word.to_string()).collect();
+        self
+    }
+
+    /// Returns whether a term is a stop word.
+    ///
+    /// Stop words are matched regardless of case.
+    pub fn is_stop_word(&self, term: &str) -> bool {
+        if self.stop_words.is_empty() {
+            return false;
+        }
+        if self.lowercase {
+            self.stop_words.contains(term)
+        } else {
+            self.stop_words.contains(&term.to_ascii_lowercase())
+        }
+    }
+
+    /// Splits text into the terms it would be indexed as, in order, each with
+    /// its position in the text.
+    ///
+    /// Stop words are left out, but still take up a position, exactly as they
+    /// do in a document.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roogle::search_engine::indexer::schema::Schema;
+    ///
+    /// let schema = Schema::default().with_stop_words();
+    /// let terms = schema.analyze("Steve the hair");
+    /// assert_eq!(vec![("steve".to_string(), 0), ("hair".to_string(), 2)], terms);
+    /// ```
+    pub fn analyze(&self, text: &str) -> Vec<(String, usize)> {
+        let mut terms = Vec::new();
+        doc_index::tokenize(text.as_bytes(), self, &mut |term, pos| {
+            terms.push((term, pos))
+        })
+        .expect("reading a slice cannot fail");
+        terms
+    }
+}
+
+impl Default for Schema {
+    /// Splits terms on non-letters, lowercases them and keeps stop words.
+    fn default() -> Self {
+        Self {
+            tokenizer: Tokenizer::default(),
+            lowercase: true,
+            stop_words: BTreeSet::new(),
+        }
+    }
+}
+
+impl Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} tokenizer, {}, {} stop words",
+            self.tokenizer,
+            if self.lowercase {
+                "lowercased"
+            } else {
+                "case-sensitive"
+            },
+            self.stop_words.len()
+        )
+    }
+}
@@ -0,0 +1,513 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::mem;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::doc_index::DocIndex;
+use super::index::{Index, IndexWriter};
+use super::mem_index::MemIndex;
+use super::postings::PostingList;
+use super::stats::MemoryUsage;
+
+/// Controls when segments are flushed and merged.
+#[derive(Clone, Copy, Debug)]
+pub struct MergePolicy {
+    /// The number of documents the in-memory segment holds before it is
+    /// flushed into an immutable segment.
+    pub max_segment_docs: usize,
+
+    /// The number of segments of a similar size that are merged together.
+    ///
+    /// Segments are grouped into levels, where level `n` holds segments of
+    /// up to `max_segment_docs * merge_factor^(n + 1)` documents. As soon as
+    /// a level holds `merge_factor` segments, they are merged into a single
+    /// segment on the next level up.
+    pub merge_factor: usize,
+}
+
+impl MergePolicy {
+    /// Returns the level of a segment with `num_docs` documents.
+    fn level(&self, num_docs: usize) -> usize {
+        let mut level = 0;
+        let mut cap = self.max_segment_docs.saturating_mul(self.merge_factor);
+        while num_docs >= cap && cap > 0 {
+            level += 1;
+            cap = cap.saturating_mul(self.merge_factor);
+        }
+        level
+    }
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        Self {
+            max_segment_docs: 1000,
+            merge_factor: 10,
+        }
+    }
+}
+
+/// A part of a segmented index and the documents it holds.
+struct Segment {
+    id: usize,
+    index: MemIndex,
+    doc_ids: HashSet<usize>,
+}
+
+impl Segment {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            index: MemIndex::new(),
+            doc_ids: HashSet::new(),
+        }
+    }
+
+    /// Merges segments into a new one, leaving out the deleted documents.
+    fn merge(id: usize, segments: &[Arc<Segment>], tombstones: &HashSet<usize>) -> Self {
+        let mut merged = Self::new(id);
+        let mut terms: HashMap<&str, Vec<&PostingList>> = HashMap::new();
+        for segment in segments.iter() {
+            for (term, postings) in segment.index.index.iter() {
+                terms.entry(term).or_default().push(postings);
+            }
+            let live = segment.doc_ids.difference(tombstones);
+            merged.doc_ids.extend(live);
+        }
+        for (term, lists) in terms.into_iter() {
+            let mut postings = PostingList::merge(lists);
+            postings.retain(|doc_id| !tombstones.contains(&doc_id));
+            if !postings.is_empty() {
+                merged.index.insert_postings(term.to_string(), postings);
+            }
+        }
+        merged
+    }
+}
+
+/// A merge of segments running on a background thread.
+struct Merge {
+    /// The IDs of the segments being merged.
+    inputs: HashSet<usize>,
+
+    handle: JoinHandle<Segment>,
+}
+
+/// An inverted index made of immutable segments.
+///
+/// New documents are added to an in-memory segment, which is flushed into an
+/// immutable segment once it holds `max_segment_docs` documents. Searches fan
+/// out over every segment. Whenever enough segments of a similar size pile
+/// up, they are merged into a larger one on a background thread; the merged
+/// segment replaces its inputs the next time the index is written to.
+///
+/// Deleted documents are tombstoned and dropped from their segment when it
+/// is next merged or the index is compacted.
+pub struct SegmentedIndex {
+    segments: Vec<Arc<Segment>>,
+    active: Segment,
+    next_segment_id: usize,
+    policy: MergePolicy,
+    merge: Option<Merge>,
+    tombstones: HashSet<usize>,
+}
+
+impl SegmentedIndex {
+    /// Creates a new empty SegmentedIndex.
+    pub fn new(policy: MergePolicy) -> Self {
+        Self {
+            segments: Vec::new(),
+            active: Segment::new(0),
+            next_segment_id: 1,
+            policy,
+            merge: None,
+            tombstones: HashSet::new(),
+        }
+    }
+
+    /// Returns the number of immutable segments in the index.
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns whether a merge is running in the background.
+    pub fn is_merging(&self) -> bool {
+        self.merge.is_some()
+    }
+
+    /// Flushes the in-memory segment into an immutable segment.
+    ///
+    /// This may start a background merge, according to the merge policy.
+    pub fn flush(&mut self) {
+        self.install_merge(false);
+        if !self.active.doc_ids.is_empty() {
+            let id = self.next_segment_id();
+            let segment = mem::replace(&mut self.active, Segment::new(id));
+            self.segments.push(Arc::new(segment));
+        }
+        self.maybe_merge();
+    }
+
+    /// Blocks until every pending background merge has been installed.
+    pub fn wait_for_merges(&mut self) {
+        while self.merge.is_some() {
+            self.install_merge(true);
+        }
+    }
+
+    fn next_segment_id(&mut self) -> usize {
+        let id = self.next_segment_id;
+        self.next_segment_id += 1;
+        id
+    }
+
+    /// Starts a background merge if the merge policy asks for one.
+    fn maybe_merge(&mut self) {
+        if self.merge.is_some() || self.policy.merge_factor < 2 {
+            return;
+        }
+        let mut levels: HashMap<usize, Vec<&Arc<Segment>>> = HashMap::new();
+        for segment in self.segments.iter() {
+            let level = self.policy.level(segment.doc_ids.len());
+            levels.entry(level).or_default().push(segment);
+        }
+        let candidates = levels
+            .into_iter()
+            .filter(|(_, segments)| segments.len() >= self.policy.merge_factor)
+            .min_by_key(|(level, _)| *level)
+            .map(|(_, segments)| segments);
+        let inputs: Vec<Arc<Segment>> = match candidates {
+            Some(segments) => segments
+                .into_iter()
+                .take(self.policy.merge_factor)
+                .cloned()
+                .collect(),
+            None => return,
+        };
+
+        let id = self.next_segment_id();
+        let tombstones = self.tombstones.clone();
+        let merge = Merge {
+            inputs: inputs.iter().map(|s| s.id).collect(),
+            handle: thread::spawn(move || Segment::merge(id, &inputs, &tombstones)),
+        };
+        self.merge = Some(merge);
+    }
+
+    /// Replaces the inputs of the background merge with its output.
+    ///
+    /// If `block` is false and the merge has not finished yet, then nothing
+    /// happens.
+    fn install_merge(&mut self, block: bool) {
+        match self.merge {
+            Some(ref merge) if block || merge.handle.is_finished() => {}
+            _ => return,
+        }
+        let Merge { inputs, handle } = self.merge.take().unwrap();
+        let merged = handle.join().expect("segment merge panicked");
+        self.segments.retain(|s| !inputs.contains(&s.id));
+        if !merged.doc_ids.is_empty() {
+            self.segments.push(Arc::new(merged));
+        }
+
+        // Tombstones are only needed for as long as their document is still
+        // held by some segment.
+        let segments = &self.segments;
+        let active = &self.active;
+        self.tombstones.retain(|doc_id| {
+            active.doc_ids.contains(doc_id) || segments.iter().any(|s| s.doc_ids.contains(doc_id))
+        });
+        self.maybe_merge();
+    }
+}
+
+impl Index for SegmentedIndex {
+    fn num_terms(&self) -> usize {
+        let mut terms: HashSet<&str> = HashSet::new();
+        for segment in self
+            .segments
+            .iter()
+            .map(|s| s.as_ref())
+            .chain([&self.active])
+        {
+            terms.extend(segment.index.index.keys().map(|t| t.as_str()));
+        }
+        terms.len()
+    }
+
+    fn search(&self, term: &str) -> Option<Cow<'_, PostingList>> {
+        let mut hits = self
+            .segments
+            .iter()
+            .map(|s| s.as_ref())
+            .chain([&self.active])
+            .filter_map(|segment| segment.index.index.get(term));
+        let first = hits.next()?;
+        match hits.next() {
+            None => Some(Cow::Borrowed(first)),
+            Some(second) => {
+                let mut lists = vec![first, second];
+                lists.extend(hits);
+                Some(Cow::Owned(PostingList::merge(lists)))
+            }
+        }
+    }
+
+    fn terms(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        let mut terms: HashSet<&str> = HashSet::new();
+        for segment in self
+            .segments
+            .iter()
+            .map(|s| s.as_ref())
+            .chain([&self.active])
+        {
+            terms.extend(segment.index.terms());
+        }
+        Box::new(terms.into_iter())
+    }
+
+    fn terms_with_prefix<'a, 'p>(
+        &'a self,
+        prefix: &'p str,
+    ) -> Box<dyn Iterator<Item = &'a str> + 'p>
+    where
+        'a: 'p,
+    {
+        let mut terms: BTreeSet<&str> = BTreeSet::new();
+        for segment in self
+            .segments
+            .iter()
+            .map(|s| s.as_ref())
+            .chain([&self.active])
+        {
+            terms.extend(segment.index.terms_with_prefix(prefix));
+        }
+        Box::new(terms.into_iter())
+    }
+
+    /// Segments that are being merged in the background are counted once;
+    /// the merged segment they are turning into is not counted yet.
+    fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for segment in self
+            .segments
+            .iter()
+            .map(|s| s.as_ref())
+            .chain([&self.active])
+        {
+            let segment_usage = segment.index.memory_usage();
+            usage.dictionary += segment_usage.dictionary;
+            usage.postings += segment_usage.postings;
+        }
+        usage
+    }
+
+    fn is_deleted(&self, doc_id: usize) -> bool {
+        self.tombstones.contains(&doc_id)
+    }
+}
+
+impl IndexWriter for SegmentedIndex {
+    /// The document is added to the in-memory segment, which is flushed if it
+    /// is full.
+    fn add(&mut self, doc_index: DocIndex, doc_id: usize) {
+        self.install_merge(false);
+        self.active.index.add(doc_index, doc_id);
+        self.active.doc_ids.insert(doc_id);
+        if self.active.doc_ids.len() >= self.policy.max_segment_docs {
+            self.flush();
+        }
+    }
+
+    fn delete(&mut self, doc_id: usize) {
+        self.tombstones.insert(doc_id);
+    }
+
+    /// Every segment holding a deleted document is rewritten without it, so
+    /// this waits for the running background merge first.
+    fn compact(&mut self) {
+        self.wait_for_merges();
+        let tombstones = mem::take(&mut self.tombstones);
+        for doc_id in tombstones.iter() {
+            self.active.index.delete(*doc_id);
+        }
+        self.active.index.compact();
+        self.active
+            .doc_ids
+            .retain(|doc_id| !tombstones.contains(doc_id));
+        for segment in self.segments.iter_mut() {
+            if segment.doc_ids.is_disjoint(&tombstones) {
+                continue;
+            }
+            let compacted = Segment::merge(segment.id, std::slice::from_ref(segment), &tombstones);
+            *segment = Arc::new(compacted);
+        }
+        self.segments.retain(|s| !s.doc_ids.is_empty());
+    }
+}
+
+impl Default for SegmentedIndex {
+    fn default() -> Self {
+        Self::new(MergePolicy::default())
+    }
+}
+
+impl Display for SegmentedIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SegmentedIndex(")?;
+        for segment in self.segments.iter() {
+            write!(f, "{}: {} docs, ", segment.id, segment.doc_ids.len())?;
+        }
+        write!(f, "active: {} docs)", self.active.doc_ids.len())
+    }
+}
+
+impl Debug for SegmentedIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::indexer::schema::Schema;
+    use std::ops::Range;
+
+    const WORDS: [&str; 6] = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"];
+
+    /// Adds a document to `index` for each word in `doc_ids`, under the
+    /// word's index.
+    fn add_docs<W: IndexWriter>(index: &mut W, doc_ids: Range<usize>) {
+        for (doc_id, word) in doc_ids.clone().zip(WORDS[doc_ids].iter()) {
+            let parity = if doc_id % 2 == 0 { "even" } else { "odd" };
+            let text = format!("{} common {} common", word, parity);
+            index.add(DocIndex::from_text(word, &text, &Schema::default()), doc_id);
+        }
+    }
+
+    /// Returns the documents a term occurs in that are not deleted.
+    fn docs_of<I: Index>(index: &I, term: &str) -> Vec<(usize, Vec<usize>)> {
+        let postings = match index.search(term) {
+            Some(postings) => postings,
+            None => return Vec::new(),
+        };
+        postings
+            .iter()
+            .filter(|(doc_id, _)| !index.is_deleted(*doc_id))
+            .map(|(doc_id, positions)| (doc_id, positions.collect()))
+            .collect()
+    }
+
+    /// Checks that `index` matches the same documents as a `MemIndex` of the
+    /// first `n` words without the `deleted` documents.
+    fn assert_matches<I: Index>(index: &I, n: usize, deleted: &[usize]) {
+        let mut expected = MemIndex::new();
+        add_docs(&mut expected, 0..n);
+        for doc_id in deleted.iter() {
+            expected.delete(*doc_id);
+        }
+        for term in WORDS.iter().chain(["common", "even", "odd"].iter()) {
+            assert_eq!(docs_of(&expected, term), docs_of(index, term), "{}", term);
+        }
+    }
+
+    #[test]
+    fn flush() {
+        let policy = MergePolicy {
+            max_segment_docs: 2,
+            merge_factor: 10,
+        };
+        let mut index = SegmentedIndex::new(policy);
+        add_docs(&mut index, 0..5);
+        assert_eq!(2, index.num_segments());
+        assert_eq!(1, index.active.doc_ids.len());
+        assert_matches(&index, 5, &[]);
+
+        index.flush();
+        assert_eq!(3, index.num_segments());
+        assert!(index.active.doc_ids.is_empty());
+        assert_matches(&index, 5, &[]);
+
+        // Flushing an empty segment does nothing.
+        index.flush();
+        assert_eq!(3, index.num_segments());
+    }
+
+    #[test]
+    fn background_merge() {
+        let policy = MergePolicy {
+            max_segment_docs: 1,
+            merge_factor: 2,
+        };
+        let mut index = SegmentedIndex::new(policy);
+        add_docs(&mut index, 0..2);
+        // The merge is only installed on the next write.
+        assert!(index.is_merging());
+        assert_matches(&index, 2, &[]);
+
+        add_docs(&mut index, 2..4);
+        index.wait_for_merges();
+        assert!(!index.is_merging());
+        assert_eq!(1, index.num_segments());
+        assert_eq!(4, index.segments[0].doc_ids.len());
+        assert_matches(&index, 4, &[]);
+    }
+
+    #[test]
+    fn delete_during_merge() {
+        let policy = MergePolicy {
+            max_segment_docs: 1,
+            merge_factor: 2,
+        };
+        let mut index = SegmentedIndex::new(policy);
+        add_docs(&mut index, 0..2);
+        assert!(index.is_merging());
+        // The merge started before the delete, so its output still holds the
+        // document, which must stay deleted once it is installed.
+        index.delete(1);
+        assert_matches(&index, 2, &[1]);
+
+        index.wait_for_merges();
+        assert!(index.is_deleted(1));
+        assert!(index.search("bravo").is_some());
+        assert_matches(&index, 2, &[1]);
+
+        // The next merge of the segment holding it drops it for good.
+        add_docs(&mut index, 2..4);
+        index.wait_for_merges();
+        assert!(!index.is_deleted(1));
+        assert!(index.search("bravo").is_none());
+        assert_matches(&index, 4, &[1]);
+    }
+
+    #[test]
+    fn compact() {
+        let policy = MergePolicy {
+            max_segment_docs: 2,
+            merge_factor: 10,
+        };
+        let mut index = SegmentedIndex::new(policy);
+        add_docs(&mut index, 0..5);
+        // One document in a segment, and one in the in-memory segment.
+        index.delete(0);
+        index.delete(4);
+        assert_matches(&index, 5, &[0, 4]);
+
+        index.compact();
+        assert!(index.tombstones.is_empty());
+        assert!(index.search("alpha").is_none());
+        assert!(index.search("echo").is_none());
+        assert_eq!(2, index.num_segments());
+        assert_matches(&index, 5, &[0, 4]);
+
+        // A segment left empty is dropped.
+        index.delete(2);
+        index.delete(3);
+        index.compact();
+        assert_eq!(1, index.num_segments());
+        assert_matches(&index, 5, &[0, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use super::doc_table::DocTable;
+use super::index::Index;
+use super::postings::PostingList;
+
+/// How often a single term occurs in the index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermStats {
+    /// The term.
+    pub term: String,
+
+    /// The number of documents the term occurs in.
+    pub doc_freq: usize,
+
+    /// The number of times the term occurs across all documents.
+    pub total_freq: usize,
+}
+
+impl TermStats {
+    /// Counts the occurrences of a term in its postings.
+    ///
+    /// Postings of documents that were deleted from `index` are not counted.
+    pub fn from_postings<I: Index + ?Sized>(term: &str, postings: &PostingList, index: &I) -> Self {
+        let mut stats = Self {
+            term: term.to_string(),
+            doc_freq: 0,
+            total_freq: 0,
+        };
+        for (doc_id, positions) in postings.iter() {
+            if !index.is_deleted(doc_id) {
+                stats.doc_freq += 1;
+                stats.total_freq += positions.len();
+            }
+        }
+        stats
+    }
+}
+
+/// An estimate of the heap memory used by an index, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The doc table: document names, IDs and file fingerprints.
+    pub doc_table: usize,
+
+    /// The term dictionary: the terms and the tables that look them up.
+    pub dictionary: usize,
+
+    /// The posting lists held on the heap.
+    pub postings: usize,
+
+    /// Index data that is memory-mapped rather than held on the heap.
+    pub mapped: usize,
+}
+
+impl MemoryUsage {
+    /// The total heap memory used by the index.
+    pub fn total(&self) -> usize {
+        self.doc_table + self.dictionary + self.postings
+    }
+}
+
+/// The distribution of document lengths, in terms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LengthDistribution {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: usize,
+    pub p90: usize,
+    pub p99: usize,
+
+    /// The number of documents whose length falls in each power-of-two
+    /// bucket. Bucket `i` counts the documents of length `[2^i, 2^(i+1))`,
+    /// except bucket 0, which also counts empty documents.
+    pub histogram: Vec<usize>,
+}
+
+impl LengthDistribution {
+    /// Computes the distribution of a list of document lengths.
+    pub fn from_lengths(mut lengths: Vec<usize>) -> Self {
+        if lengths.is_empty() {
+            return Self::default();
+        }
+        lengths.sort_unstable();
+        let percentile = |p: usize| lengths[(lengths.len() - 1) * p / 100];
+        let mut histogram = Vec::new();
+        for len in lengths.iter() {
+            let bucket = (usize::BITS - len.leading_zeros()).saturating_sub(1) as usize;
+            if histogram.len() <= bucket {
+                histogram.resize(bucket + 1, 0);
+            }
+            histogram[bucket] += 1;
+        }
+        Self {
+            min: lengths[0],
+            max: lengths[lengths.len() - 1],
+            mean: lengths.iter().sum::<usize>() as f64 / lengths.len() as f64,
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            histogram,
+        }
+    }
+}
+
+/// Statistics about the contents of an index.
+#[derive(Clone, Debug, Default)]
+pub struct IndexStats {
+    /// The number of documents in the index.
+    pub num_docs: usize,
+
+    /// The number of distinct terms in the index.
+    pub num_terms: usize,
+
+    /// The number of (term, document) pairs in the index.
+    pub num_postings: usize,
+
+    /// The number of term occurrences in the index.
+    pub num_positions: usize,
+
+    /// The average number of documents per term.
+    pub avg_postings_len: f64,
+
+    /// The most common terms, by total frequency.
+    pub top_terms: Vec<TermStats>,
+
+    /// The distribution of document lengths.
+    pub doc_lengths: LengthDistribution,
+
+    /// An estimate of the memory used by the index.
+    pub memory: MemoryUsage,
+}
+
+impl IndexStats {
+    /// Collects statistics by walking every posting list in the index.
+    ///
+    /// `top_n` is the number of most common terms to report.
+    pub fn collect<I: Index + ?Sized>(doc_table: &DocTable, index: &I, top_n: usize) -> Self {
+        let mut stats = Self {
+            num_docs: doc_table.num_docs(),
+            ..Self::default()
+        };
+        let mut doc_lengths: HashMap<usize, usize> = doc_table.ids().map(|id| (id, 0)).collect();
+        let mut terms: Vec<TermStats> = Vec::new();
+        for term in index.terms() {
+            let postings = match index.search(term) {
+                Some(postings) => postings,
+                None => continue,
+            };
+            for (doc_id, positions) in postings.iter() {
+                if let Some(len) = doc_lengths.get_mut(&doc_id) {
+                    *len += positions.len();
+                }
+            }
+            let term_stats = TermStats::from_postings(term, &postings, index);
+            if term_stats.doc_freq > 0 {
+                stats.num_postings += term_stats.doc_freq;
+                stats.num_positions += term_stats.total_freq;
+                terms.push(term_stats);
+            }
+        }
+
+        stats.num_terms = terms.len();
+        if stats.num_terms > 0 {
+            stats.avg_postings_len = stats.num_postings as f64 / stats.num_terms as f64;
+        }
+        terms.sort_by(|a, b| {
+            b.total_freq
+                .cmp(&a.total_freq)
+                .then(b.doc_freq.cmp(&a.doc_freq))
+                .then(a.term.cmp(&b.term))
+        });
+        terms.truncate(top_n);
+        stats.top_terms = terms;
+        stats.doc_lengths = LengthDistribution::from_lengths(doc_lengths.into_values().collect());
+        stats.memory = MemoryUsage {
+            doc_table: doc_table.memory_usage(),
+            ..index.memory_usage()
+        };
+        stats
+    }
+}
+
+impl Display for IndexStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Documents:          {}", self.num_docs)?;
+        writeln!(f, "Terms:              {}", self.num_terms)?;
+        writeln!(f, "Postings:           {}", self.num_postings)?;
+        writeln!(f, "Term occurrences:   {}", self.num_positions)?;
+        writeln!(f, "Avg postings len:   {:.2}", self.avg_postings_len)?;
+        writeln!(f)?;
+
+        let lengths = &self.doc_lengths;
+        writeln!(f, "Document lengths (terms):")?;
+        writeln!(
+            f,
+            "  min {}  median {}  mean {:.1}  p90 {}  p99 {}  max {}",
+            lengths.min, lengths.median, lengths.mean, lengths.p90, lengths.p99, lengths.max
+        )?;
+        for (bucket, count) in lengths.histogram.iter().enumerate() {
+            let low = if bucket == 0 { 0 } else { 1usize << bucket };
+            let high = (1usize << (bucket + 1)) - 1;
+            writeln!(f, "  {:>8} - {:<8} {}", low, high, count)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "Most common terms (term, total freq, doc freq):")?;
+        for term in self.top_terms.iter() {
+            writeln!(
+                f,
+                "  {:<20} {:>10} {:>8}",
+                term.term, term.total_freq, term.doc_freq
+            )?;
+        }
+        writeln!(f)?;
+
+        let memory = &self.memory;
+        writeln!(f, "Estimated memory:")?;
+        writeln!(f, "  doc table          {}", human_bytes(memory.doc_table))?;
+        writeln!(f, "  term dictionary    {}", human_bytes(memory.dictionary))?;
+        writeln!(f, "  postings           {}", human_bytes(memory.postings))?;
+        if memory.mapped > 0 {
+            writeln!(f, "  memory-mapped      {}", human_bytes(memory.mapped))?;
+        }
+        write!(f, "  total (heap)       {}", human_bytes(memory.total()))
+    }
+}
+
+/// Formats a number of bytes with a binary unit.
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
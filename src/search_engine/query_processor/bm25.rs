@@ -0,0 +1,89 @@
+use super::scorer::{Scorer, TermMatch};
+
+/// The parameters of the BM25 ranking function.
+///
+/// BM25 scores a term in a document higher the more often it occurs there,
+/// the fewer documents it occurs in, and the shorter the document is:
+///
+/// ```text
+/// idf(t)    = ln(1 + (N - df(t) + 0.5) / (df(t) + 0.5))
+/// score(t)  = idf(t) * tf * (k1 + 1) / (tf + k1 * (1 - b + b * len / avg_len))
+/// ```
+///
+/// where `N` is the number of documents, `df(t)` the number of documents the
+/// term occurs in, `tf` the number of times it occurs in the document, and
+/// `len` and `avg_len` the length of the document and the average length of
+/// a document, in terms.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::query_processor::bm25::Bm25;
+/// use roogle::search_engine::query_processor::scorer::{Scorer, TermMatch};
+///
+/// let bm25 = Bm25::default();
+/// let term = TermMatch {
+///     term: "hair".into(),
+///     term_freq: 1,
+///     doc_freq: 1,
+///     num_docs: 10,
+///     doc_len: 100,
+///     avg_doc_len: 100.0,
+/// };
+/// // A rarer term scores higher...
+/// let common = TermMatch { doc_freq: 5, ..term.clone() };
+/// assert!(bm25.score_term(&term) > bm25.score_term(&common));
+/// // ...and so does a term in a shorter document.
+/// let short = TermMatch { doc_len: 50, ..term.clone() };
+/// assert!(bm25.score_term(&short) > bm25.score_term(&term));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    /// How quickly repeated occurrences of a term stop raising its score.
+    ///
+    /// At 0, a term scores the same however often it occurs; the larger it
+    /// is, the longer the score keeps growing with every occurrence.
+    pub k1: f64,
+
+    /// How much the score of a term is lowered in long documents, from 0
+    /// (not at all) to 1 (in proportion to the length of the document).
+    pub b: f64,
+}
+
+impl Bm25 {
+    /// Returns the inverse document frequency of a term that occurs in
+    /// `doc_freq` of `num_docs` documents.
+    pub fn idf(&self, doc_freq: usize, num_docs: usize) -> f64 {
+        let doc_freq = doc_freq.min(num_docs) as f64;
+        (1.0 + (num_docs as f64 - doc_freq + 0.5) / (doc_freq + 0.5)).ln()
+    }
+}
+
+impl Scorer for Bm25 {
+    fn score_term(&self, term: &TermMatch) -> f64 {
+        let tf = term.term_freq as f64;
+        let len_ratio = if term.avg_doc_len > 0.0 {
+            term.doc_len as f64 / term.avg_doc_len
+        } else {
+            1.0
+        };
+        let norm = self.k1 * (1.0 - self.b + self.b * len_ratio);
+        self.idf(term.doc_freq, term.num_docs) * tf * (self.k1 + 1.0) / (tf + norm)
+    }
+
+    /// A term scores less than `idf * (k1 + 1)` however often it occurs, as
+    /// long as `k1` is not negative and `b` is between 0 and 1.
+    fn max_term_score(&self, doc_freq: usize, num_docs: usize) -> Option<f64> {
+        if self.k1 < 0.0 || !(0.0..=1.0).contains(&self.b) {
+            return None;
+        }
+        Some(self.idf(doc_freq, num_docs) * (self.k1 + 1.0))
+    }
+}
+
+impl Default for Bm25 {
+    /// The usual parameters: `k1 = 1.2` and `b = 0.75`.
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
@@ -0,0 +1,21 @@
+use super::intersect::gallop;
+use super::query_result::QueryResult;
+
+pub trait Difference {
+    /// Removes the results that are also in the other query results vector.
+    fn difference(&mut self, other: &[QueryResult]);
+}
+
+impl Difference for Vec<QueryResult> {
+    /// Both vectors must be sorted by document ID.
+    ///
+    /// Like `intersect`, the other vector is searched with a galloping search
+    /// from where the previous lookup left off.
+    fn difference(&mut self, other: &[QueryResult]) {
+        let mut rest = other;
+        self.retain(|a: &QueryResult| {
+            rest = &rest[gallop(rest, a.doc_id)..];
+            !matches!(rest.first(), Some(b) if a.doc_id == b.doc_id)
+        });
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+/// How a document was scored by a query, or why it did not match.
+///
+/// An explanation mirrors the structure of the query: the explanation of an
+/// `AND` or `OR` holds the explanations of its clauses, and the explanation of
+/// a phrase or wildcard holds those of its terms. The score of a matching node
+/// is the score it adds to the document; the score at the root is the score
+/// the document is listed with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    /// Whether the document matches this part of the query.
+    pub matched: bool,
+
+    /// The score this part of the query adds to the document.
+    pub score: f64,
+
+    /// What was matched and how it was scored.
+    pub description: String,
+
+    /// The explanations of the parts this score was computed from.
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    /// Creates an explanation of a match.
+    pub fn matched(score: f64, description: String, details: Vec<Explanation>) -> Self {
+        Self {
+            matched: true,
+            score,
+            description,
+            details,
+        }
+    }
+
+    /// Creates an explanation of why a document did not match.
+    pub fn unmatched(description: String, details: Vec<Explanation>) -> Self {
+        Self {
+            matched: false,
+            score: 0.0,
+            description,
+            details,
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        if self.matched {
+            writeln!(f, "{}{:.3} {}", indent, self.score, self.description)?;
+        } else {
+            writeln!(f, "{}- {}", indent, self.description)?;
+        }
+        for detail in self.details.iter() {
+            detail.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the explanation as a tree, one part per line, each preceded by its
+/// score, or by `-` if it did not match.
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::query_token::wildcard_match;
+
+/// A piece of document metadata that a query can be restricted by.
+///
+/// A field is written as a prefix of a query term, like `ext:md`. Fields are
+/// matched against the path of a document, not its contents, and always
+/// regardless of case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The path of the document. `path:docs/api` matches every document with
+    /// `docs/api` in its path, starting at a directory or file name, so it
+    /// matches `./docs/api/intro.md` but not `./mydocs/api.md`.
+    Path,
+
+    /// The file name of the document, with or without its extension.
+    /// `name:readme` matches `./README.md` and `./docs/readme`.
+    Name,
+
+    /// The extension of the document. `ext:md` matches `./README.md`.
+    Ext,
+}
+
+impl Field {
+    /// Returns whether the document at `path` matches `value` in this field.
+    ///
+    /// A name or extension can contain the wildcards `*` and `?`, as in
+    /// `name:test_*`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use roogle::search_engine::query_processor::field::Field;
+    ///
+    /// assert!(Field::Path.matches("docs/api", "./docs/api/intro.md"));
+    /// assert!(!Field::Path.matches("docs/api", "./mydocs/api.md"));
+    /// assert!(Field::Name.matches("readme", "./README.md"));
+    /// assert!(Field::Ext.matches(".MD", "./README.md"));
+    /// ```
+    pub fn matches(&self, value: &str, path: &str) -> bool {
+        let value = value.to_lowercase();
+        let path = path.to_lowercase();
+        match self {
+            Field::Path => path
+                .match_indices(value.as_str())
+                .any(|(i, _)| i == 0 || path[..i].ends_with('/')),
+            Field::Name => {
+                let path = Path::new(&path);
+                let stem = path.file_stem().and_then(|s| s.to_str());
+                let name = path.file_name().and_then(|n| n.to_str());
+                [stem, name]
+                    .iter()
+                    .flatten()
+                    .any(|name| wildcard_match(&value, name))
+            }
+            Field::Ext => {
+                let value = value.strip_prefix('.').unwrap_or(&value);
+                Path::new(&path)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| wildcard_match(value, ext))
+            }
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Field::Path),
+            "name" => Ok(Field::Name),
+            "ext" => Ok(Field::Ext),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Field::Path => write!(f, "path"),
+            Field::Name => write!(f, "name"),
+            Field::Ext => write!(f, "ext"),
+        }
+    }
+}
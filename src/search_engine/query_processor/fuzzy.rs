@@ -0,0 +1,95 @@
+use crate::search_engine::indexer::index::Index;
+
+/// The largest edit distance a fuzzy term can be matched with.
+pub const MAX_EDITS: usize = 2;
+
+/// Returns the edit distance typos are tolerated within for a term, based on
+/// its length: none for terms of up to 2 characters, 1 for terms of 3 to 5
+/// characters, and 2 for longer terms.
+pub fn auto_max_edits(term: &str) -> usize {
+    match term.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Returns the terms in the index within `max_edits` edits of `term`.
+///
+/// An edit is the insertion, deletion or substitution of a single character
+/// (the Levenshtein distance). The terms are returned with their distance,
+/// closest first, then in sorted order; `term` itself is included if it is in
+/// the index.
+///
+/// The dictionary is walked in sorted order, so the rows of the distance
+/// table are shared between terms with a common prefix, and every term that
+/// starts with a prefix already more than `max_edits` edits away is skipped
+/// without being compared.
+pub fn fuzzy_terms<'a, I: Index + ?Sized>(
+    index: &'a I,
+    term: &str,
+    max_edits: usize,
+) -> Vec<(&'a str, usize)> {
+    let target: Vec<char> = term.chars().collect();
+    let mut matches: Vec<(&str, usize)> = Vec::new();
+
+    // rows[i] holds the distances between the first i characters of the
+    // previous candidate and every prefix of the target.
+    let mut rows: Vec<Vec<usize>> = vec![(0..=target.len()).collect()];
+    let mut prev: Vec<char> = Vec::new();
+    let mut dead_prefix: Option<Vec<char>> = None;
+
+    for candidate in index.terms_with_prefix("") {
+        let chars: Vec<char> = candidate.chars().collect();
+        if let Some(dead) = &dead_prefix {
+            if chars.starts_with(dead) {
+                continue;
+            }
+            dead_prefix = None;
+        }
+
+        let common = chars
+            .iter()
+            .zip(prev.iter())
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(rows.len() - 1);
+        rows.truncate(common + 1);
+        let mut alive = true;
+        for i in common..chars.len() {
+            let row = next_row(&rows[i], chars[i], &target);
+            let min = *row.iter().min().unwrap();
+            rows.push(row);
+            if min > max_edits {
+                // No term starting with chars[..=i] can be close enough.
+                dead_prefix = Some(chars[..=i].to_vec());
+                alive = false;
+                break;
+            }
+        }
+        if alive {
+            let distance = rows[chars.len()][target.len()];
+            if distance <= max_edits {
+                matches.push((candidate, distance));
+            }
+        }
+        prev = chars;
+    }
+
+    matches.sort_by_key(|&(term, distance)| (distance, term));
+    matches
+}
+
+/// Computes the next row of the distance table after appending `c` to the
+/// candidate.
+fn next_row(row: &[usize], c: char, target: &[char]) -> Vec<usize> {
+    let mut next = Vec::with_capacity(row.len());
+    next.push(row[0] + 1);
+    for (j, &t) in target.iter().enumerate() {
+        let substitute = row[j] + (c != t) as usize;
+        let delete = row[j + 1] + 1;
+        let insert = next[j] + 1;
+        next.push(substitute.min(delete).min(insert));
+    }
+    next
+}
@@ -2,20 +2,38 @@ use super::query_result::QueryResult;
 
 pub trait Intersect {
     /// Returns a vector that is the intersection of this and the other query results vector.
-    fn intersect(&mut self, other: &Vec<QueryResult>);
+    fn intersect(&mut self, other: &[QueryResult]);
 }
 
 impl Intersect for Vec<QueryResult> {
-    fn intersect(&mut self, other: &Vec<QueryResult>) {
+    /// Both vectors must be sorted by document ID.
+    ///
+    /// The vectors are merged in a single pass. For every result kept from
+    /// this vector, the other vector is searched with a galloping (exponential)
+    /// search from where the previous match left off, so intersecting a short
+    /// vector with a long one only touches a few results of the long one.
+    fn intersect(&mut self, other: &[QueryResult]) {
+        let mut rest = other;
         self.retain_mut(|a: &mut QueryResult| {
-            other.iter().any(|b: &QueryResult| {
-                if a.doc_id == b.doc_id {
-                    a.rank += b.rank; // increment rank
+            rest = &rest[gallop(rest, a.doc_id)..];
+            match rest.first() {
+                Some(b) if a.doc_id == b.doc_id => {
+                    a.merge(b);
                     true
-                } else {
-                    false
                 }
-            })
+                _ => false,
+            }
         });
     }
 }
+
+/// Returns the index of the first result whose document ID is at least
+/// `doc_id`, in a vector sorted by document ID.
+pub(super) fn gallop(results: &[QueryResult], doc_id: usize) -> usize {
+    let mut bound = 1;
+    while bound < results.len() && results[bound - 1].doc_id < doc_id {
+        bound *= 2;
+    }
+    let end = bound.min(results.len());
+    results[..end].partition_point(|r| r.doc_id < doc_id)
+}
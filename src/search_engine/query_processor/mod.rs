@@ -1,4 +1,17 @@
+pub mod bm25;
+pub mod difference;
+pub mod explain;
+pub mod field;
+pub mod fuzzy;
+pub mod intersect;
+pub mod query_parser;
+#[allow(clippy::module_inception)]
 pub mod query_processor;
 pub mod query_result;
 pub mod query_token;
-pub mod intersect;
+pub mod regex_term;
+pub mod scorer;
+pub mod search_options;
+pub mod suggest;
+pub mod top_k;
+pub mod union;
@@ -0,0 +1,845 @@
+use std::fmt::{Debug, Display};
+use std::{iter, mem};
+
+use crate::search_engine::indexer::doc_table::DocTable;
+use crate::search_engine::indexer::index::Index;
+use crate::search_engine::indexer::schema::Schema;
+
+use super::difference::Difference;
+use super::explain::Explanation;
+use super::field::Field;
+use super::fuzzy::MAX_EDITS;
+use super::intersect::Intersect;
+use super::query_result::QueryResult;
+use super::query_token::QueryToken;
+use super::regex_term::build_regex;
+use super::scorer::Scorer;
+use super::search_options::SearchOptions;
+use super::union::Union;
+
+/// A parsed query.
+///
+/// The leaves of a query are terms and phrases, which are combined with
+/// `AND`, `OR` and `NOT`. For example, `(rust OR golang) "error handling"
+/// -java` parses to:
+///
+/// ```text
+/// And(Or(Term(rust), Term(golang)), Phrase(error handling), Not(Term(java)))
+/// ```
+#[derive(PartialEq)]
+pub enum Query {
+    /// A single term or phrase.
+    Token(QueryToken),
+
+    /// Matches the documents that match all of the queries.
+    And(Vec<Query>),
+
+    /// Matches the documents that match any of the queries.
+    Or(Vec<Query>),
+
+    /// Matches the documents that do not match the query.
+    ///
+    /// Within an `And`, it removes the documents it matches from the results.
+    /// On its own, as in `-java` or `rust OR NOT java`, it matches every
+    /// document that it does not exclude.
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Searches the index for results that match the query.
+    ///
+    /// The results are sorted by document ID. Every term and phrase is scored
+    /// by `scorer`, and the score of a result is the sum of the scores it has
+    /// for each term and phrase of the query it matches; excluded terms and
+    /// phrases do not count towards it.
+    pub fn search<I: Index + ?Sized>(
+        &self,
+        index: &I,
+        docs: &DocTable,
+        scorer: &dyn Scorer,
+        options: &SearchOptions,
+    ) -> Vec<QueryResult> {
+        match self {
+            Query::Token(token) => token.search(index, docs, scorer, options),
+            Query::And(queries) => {
+                let (excluded, included): (Vec<&Query>, Vec<&Query>) =
+                    queries.iter().partition(|q| matches!(q, Query::Not(_)));
+                let mut meta_results: Vec<Vec<QueryResult>> = included
+                    .iter()
+                    .map(|q| q.search(index, docs, scorer, options))
+                    .collect();
+
+                // Intersect the results, starting from the shortest list so
+                // that the intermediate results stay as small as possible. If
+                // every query is excluded, then start from every document.
+                meta_results.sort_by_key(|results| results.len());
+                let mut itr = meta_results.into_iter();
+                let mut results = itr.next().unwrap_or_else(|| all_docs(docs));
+                for next in itr {
+                    if results.is_empty() {
+                        break;
+                    }
+                    results.intersect(&next);
+                }
+
+                // Then drop the documents that match an excluded query.
+                for query in excluded.into_iter() {
+                    if let Query::Not(query) = query {
+                        if results.is_empty() {
+                            break;
+                        }
+                        results.difference(&query.search(index, docs, scorer, options));
+                    }
+                }
+                results
+            }
+            Query::Or(queries) => {
+                let mut results: Vec<QueryResult> = Vec::new();
+                for query in queries.iter() {
+                    results.union(&query.search(index, docs, scorer, options));
+                }
+                results
+            }
+            Query::Not(query) => {
+                let mut results = all_docs(docs);
+                results.difference(&query.search(index, docs, scorer, options));
+                results
+            }
+        }
+    }
+
+    /// Explains how the query scores a document, or why it does not match.
+    ///
+    /// The score of every part of the query is computed as `search` computes
+    /// it: the clauses of an `AND` must all match and those of an `OR` only
+    /// need one to, and either way the scores of the matching clauses are
+    /// added up.
+    pub fn explain<I: Index + ?Sized>(
+        &self,
+        index: &I,
+        docs: &DocTable,
+        scorer: &dyn Scorer,
+        options: &SearchOptions,
+        doc_id: usize,
+    ) -> Explanation {
+        match self {
+            Query::Token(token) => token.explain(index, docs, scorer, options, doc_id),
+            Query::And(queries) => {
+                let details: Vec<Explanation> = queries
+                    .iter()
+                    .map(|q| q.explain(index, docs, scorer, options, doc_id))
+                    .collect();
+                let matching = details.iter().filter(|detail| detail.matched).count();
+                if matching < details.len() {
+                    let description = format!(
+                        "And: only {} of its {} clauses match",
+                        matching,
+                        details.len()
+                    );
+                    return Explanation::unmatched(description, details);
+                }
+                let score = details.iter().map(|detail| detail.score).sum();
+                let description = "And: every clause matches, so their scores are added";
+                Explanation::matched(score, description.to_string(), details)
+            }
+            Query::Or(queries) => {
+                let details: Vec<Explanation> = queries
+                    .iter()
+                    .map(|q| q.explain(index, docs, scorer, options, doc_id))
+                    .collect();
+                let matching = details.iter().filter(|detail| detail.matched).count();
+                if matching == 0 {
+                    let description = "Or: none of its clauses match";
+                    return Explanation::unmatched(description.to_string(), details);
+                }
+                let score = details.iter().map(|detail| detail.score).sum();
+                let description = format!(
+                    "Or: {} of its {} clauses match, so their scores are added",
+                    matching,
+                    details.len()
+                );
+                Explanation::matched(score, description, details)
+            }
+            Query::Not(query) => {
+                let detail = query.explain(index, docs, scorer, options, doc_id);
+                if detail.matched {
+                    let description = "Not: the excluded query matches";
+                    Explanation::unmatched(description.to_string(), vec![detail])
+                } else {
+                    let description = "Not: the excluded query does not match, which adds nothing";
+                    Explanation::matched(0.0, description.to_string(), vec![detail])
+                }
+            }
+        }
+    }
+
+    /// Returns the terms and phrases of the query, in the order they appear.
+    ///
+    /// Excluded terms and phrases are included.
+    pub fn into_tokens(self) -> Vec<QueryToken> {
+        match self {
+            Query::Token(token) => vec![token],
+            Query::Not(query) => query.into_tokens(),
+            Query::And(queries) | Query::Or(queries) => {
+                queries.into_iter().flat_map(Query::into_tokens).collect()
+            }
+        }
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (name, queries) = match self {
+            Query::Token(token) => return write!(f, "{}", token),
+            Query::Not(query) => return write!(f, "Not({})", query),
+            Query::And(queries) => ("And", queries),
+            Query::Or(queries) => ("Or", queries),
+        };
+        write!(f, "{}(", name)?;
+        for (i, query) in queries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", query)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Debug for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Returns every document in the doc table, sorted by document ID.
+fn all_docs(docs: &DocTable) -> Vec<QueryResult> {
+    let mut ids: Vec<usize> = docs.ids().collect();
+    ids.sort_unstable();
+    ids.into_iter()
+        .map(|id| QueryResult::from(id, 0.0, Vec::new(), docs))
+        .collect()
+}
+
+/// Parses a query string into a `Query`.
+///
+/// The grammar of a query is:
+///
+/// ```text
+/// query  := or
+/// or     := and ("OR" and)*
+/// and    := near ("AND"? near)*
+/// near   := unary ("NEAR" ("/" slop)? unary)*
+/// unary  := ("NOT" | "-") unary | "(" or ")" | "\"" phrase "\"" ("~" slop)?
+///         | "/" regex "/" | term
+/// ```
+///
+/// Terms and phrases next to each other are implicitly ANDed. What each kind
+/// of term matches is described on `QueryToken`, and every term is analyzed
+/// with `schema`, exactly as documents are when they are indexed.
+///
+/// # Errors
+/// * If the query is malformed, then a `QueryParseError` is returned with the
+///   position of the problem.
+/// * If nothing is left of the query once it is analyzed, then an error is
+///   returned too.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::indexer::schema::Schema;
+/// use roogle::search_engine::query_processor::query_parser::parse_query;
+///
+/// let query = parse_query("(rust OR golang) \"error handling\"", &Schema::default());
+/// assert_eq!(
+///     "And(Or(Term(rust), Term(golang)), Phrase(error handling))",
+///     query.unwrap().to_string()
+/// );
+///
+/// let query = parse_query("hair -\"the hair\" NOT steve", &Schema::default());
+/// assert_eq!(
+///     "And(Term(hair), Not(Phrase(the hair)), Not(Term(steve)))",
+///     query.unwrap().to_string()
+/// );
+///
+/// let query = parse_query("\"steve hair\"~5 OR steve NEAR/3 hair", &Schema::default());
+/// assert_eq!(
+///     "Or(Phrase(steve hair)~5, Near/3(steve hair))",
+///     query.unwrap().to_string()
+/// );
+///
+/// let error = parse_query("steve OR \"the hair", &Schema::default()).unwrap_err();
+/// assert_eq!("unclosed phrase at position 9", error.to_string());
+/// ```
+pub fn parse_query(query: &str, schema: &Schema) -> Result<Query, QueryParseError> {
+    let mut parser = Parser {
+        lexemes: lex(query)?,
+        pos: 0,
+        schema,
+    };
+    let parsed = parser.parse_or()?;
+    if let Some((position, _)) = parser.peek() {
+        // parse_or only stops early at a closing parenthesis.
+        return Err(QueryParseError::new(
+            *position,
+            "unmatched closing parenthesis",
+        ));
+    }
+    parsed.ok_or_else(|| QueryParseError::new(0, "nothing to search for"))
+}
+
+/// An error in the syntax of a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryParseError {
+    /// The position of the problem in the query, in characters.
+    pub position: usize,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl QueryParseError {
+    fn new(position: usize, message: &str) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for QueryParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+/// A lexical unit of a query string.
+#[derive(Clone, Debug, PartialEq)]
+enum Lexeme {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Near(usize),
+    Term(String),
+    Phrase(String, Option<usize>),
+    Regex(String),
+}
+
+impl Lexeme {
+    /// Returns whether the lexeme can start an operand of an operator.
+    fn starts_operand(&self) -> bool {
+        matches!(
+            self,
+            Lexeme::Open | Lexeme::Not | Lexeme::Term(_) | Lexeme::Phrase(..) | Lexeme::Regex(_)
+        )
+    }
+}
+
+impl Display for Lexeme {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Lexeme::Open => write!(f, "("),
+            Lexeme::Close => write!(f, ")"),
+            Lexeme::And => write!(f, "AND"),
+            Lexeme::Or => write!(f, "OR"),
+            Lexeme::Not => write!(f, "NOT"),
+            Lexeme::Near(slop) => write!(f, "NEAR/{}", slop),
+            Lexeme::Term(value) => write!(f, "{}", value),
+            Lexeme::Phrase(value, _) => write!(f, "\"{}\"", value),
+            Lexeme::Regex(pattern) => write!(f, "/{}/", pattern),
+        }
+    }
+}
+
+/// The slop of a `NEAR` without one.
+const DEFAULT_NEAR_SLOP: usize = 5;
+
+/// Splits a query string into lexemes, each with the position it starts at.
+///
+/// The operators must be uppercase; a lowercase `or` is an ordinary term. `-`
+/// only excludes what directly follows it, so a hyphen inside a word like
+/// `e-mail` is not an operator.
+fn lex(query: &str) -> Result<Vec<(usize, Lexeme)>, QueryParseError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut lexemes = Vec::new();
+    let mut word = String::new();
+    let mut start = 0;
+    let flush = |word: &mut String, start: usize, lexemes: &mut Vec<(usize, Lexeme)>| {
+        if word.is_empty() {
+            return Ok(());
+        }
+        let lexeme = match word.as_str() {
+            "AND" => Lexeme::And,
+            "OR" => Lexeme::Or,
+            "NOT" => Lexeme::Not,
+            "NEAR" => Lexeme::Near(DEFAULT_NEAR_SLOP),
+            _ => match word.strip_prefix("NEAR/") {
+                Some(slop) => Lexeme::Near(slop.parse().map_err(|_| {
+                    QueryParseError::new(start + 5, "expected a number after NEAR/")
+                })?),
+                None => Lexeme::Term(word.clone()),
+            },
+        };
+        lexemes.push((start, lexeme));
+        word.clear();
+        Ok(())
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '(' | ')' | '"' => {
+                flush(&mut word, start, &mut lexemes)?;
+                match c {
+                    '(' => lexemes.push((i, Lexeme::Open)),
+                    ')' => lexemes.push((i, Lexeme::Close)),
+                    _ => {
+                        let len = chars[i + 1..]
+                            .iter()
+                            .position(|&c| c == '"')
+                            .ok_or_else(|| QueryParseError::new(i, "unclosed phrase"))?;
+                        let phrase: String = chars[i + 1..i + 1 + len].iter().collect();
+                        if phrase.trim().is_empty() {
+                            return Err(QueryParseError::new(i, "empty phrase"));
+                        }
+                        let open = i;
+                        i += len + 1;
+
+                        // A `~` and a number right after the phrase is its slop.
+                        let mut slop = None;
+                        if chars.get(i + 1) == Some(&'~') {
+                            let digits: String = chars[i + 2..]
+                                .iter()
+                                .take_while(|c| c.is_ascii_digit())
+                                .collect();
+                            if !digits.is_empty() {
+                                slop = Some(digits.parse().map_err(|_| {
+                                    QueryParseError::new(i + 2, "slop is too large")
+                                })?);
+                                i += digits.len() + 1;
+                            }
+                        }
+                        lexemes.push((open, Lexeme::Phrase(phrase, slop)));
+                    }
+                }
+            }
+            '/' if word.is_empty() => {
+                // A regex runs up to the next `/` that is not escaped.
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != '/' {
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                if end >= chars.len() {
+                    return Err(QueryParseError::new(i, "unclosed regex"));
+                }
+                if end == i + 1 {
+                    return Err(QueryParseError::new(i, "empty regex"));
+                }
+                let pattern: String = chars[i + 1..end].iter().collect();
+                lexemes.push((i, Lexeme::Regex(pattern)));
+                i = end;
+            }
+            c if c.is_whitespace() => flush(&mut word, start, &mut lexemes)?,
+            '-' if word.is_empty() && chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                lexemes.push((i, Lexeme::Not));
+            }
+            c => {
+                if word.is_empty() {
+                    start = i;
+                }
+                word.push(c);
+            }
+        }
+        i += 1;
+    }
+    flush(&mut word, start, &mut lexemes)?;
+    Ok(lexemes)
+}
+
+/// Returns every word of the terms and phrases of a query, with the position
+/// it starts at.
+///
+/// Operators are left out, so any of the words can be replaced without
+/// changing the structure of the query.
+pub(super) fn words(query: &str) -> Result<Vec<(usize, String)>, QueryParseError> {
+    let mut words = Vec::new();
+    for (position, lexeme) in lex(query)? {
+        match lexeme {
+            Lexeme::Term(value) => words.push((position, value)),
+            Lexeme::Phrase(value, _) => {
+                // The text of a phrase starts after its opening quote.
+                let mut word = String::new();
+                for (i, c) in value.chars().chain(iter::once(' ')).enumerate() {
+                    if !c.is_whitespace() {
+                        word.push(c);
+                    } else if !word.is_empty() {
+                        let start = position + 1 + i - word.chars().count();
+                        words.push((start, mem::take(&mut word)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(words)
+}
+
+/// A recursive descent parser over the lexemes of a query.
+///
+/// Each `parse_` method returns `None` if its operands were all dropped by
+/// analysis, like a phrase of stop words.
+struct Parser<'a> {
+    lexemes: Vec<(usize, Lexeme)>,
+    pos: usize,
+    schema: &'a Schema,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&(usize, Lexeme)> {
+        self.lexemes.get(self.pos)
+    }
+
+    fn peek_lexeme(&self) -> Option<&Lexeme> {
+        self.peek().map(|(_, lexeme)| lexeme)
+    }
+
+    fn next(&mut self) -> Option<(usize, Lexeme)> {
+        let lexeme = self.lexemes.get(self.pos).cloned();
+        self.pos += 1;
+        lexeme
+    }
+
+    /// Consumes an operator, checking that an operand follows it.
+    fn next_operator(&mut self) -> Result<(), QueryParseError> {
+        let (position, operator) = self.next().unwrap();
+        match self.peek_lexeme() {
+            Some(lexeme) if lexeme.starts_operand() => Ok(()),
+            _ => Err(QueryParseError::new(
+                position,
+                &format!("{} is missing an operand", operator),
+            )),
+        }
+    }
+
+    /// Fails if an operator has nothing before it.
+    fn missing_left_operand(&self) -> QueryParseError {
+        let (position, operator) = self.peek().unwrap();
+        QueryParseError::new(*position, &format!("{} is missing an operand", operator))
+    }
+
+    fn parse_or(&mut self) -> Result<Option<Query>, QueryParseError> {
+        let start = self.pos;
+        let mut queries = Vec::new();
+        queries.extend(self.parse_and()?);
+        while self.peek_lexeme() == Some(&Lexeme::Or) {
+            if self.pos == start {
+                return Err(self.missing_left_operand());
+            }
+            self.next_operator()?;
+            queries.extend(self.parse_and()?);
+        }
+        Ok(collapse(queries, Query::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<Option<Query>, QueryParseError> {
+        let start = self.pos;
+        let mut queries = Vec::new();
+        loop {
+            match self.peek_lexeme() {
+                None | Some(Lexeme::Close) | Some(Lexeme::Or) => break,
+                Some(Lexeme::And) if self.pos > start => self.next_operator()?,
+                Some(Lexeme::And) | Some(Lexeme::Near(_)) => {
+                    return Err(self.missing_left_operand());
+                }
+                Some(_) => queries.extend(self.parse_near()?),
+            }
+        }
+        Ok(collapse(queries, Query::And))
+    }
+
+    fn parse_near(&mut self) -> Result<Option<Query>, QueryParseError> {
+        let mut queries = Vec::new();
+        let mut slop = 0;
+        queries.extend(self.parse_unary()?);
+        while let Some(&Lexeme::Near(n)) = self.peek_lexeme() {
+            self.next_operator()?;
+            slop = slop.max(n);
+            queries.extend(self.parse_unary()?);
+        }
+        Ok(near(queries, slop))
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<Query>, QueryParseError> {
+        let (position, lexeme) = match self.peek() {
+            Some(next) => next.clone(),
+            None => return Ok(None),
+        };
+        if lexeme == Lexeme::Not {
+            self.next_operator()?;
+            let query = self.parse_unary()?;
+            return Ok(query.map(|query| Query::Not(Box::new(query))));
+        }
+        self.next();
+        Ok(match lexeme {
+            Lexeme::Open => {
+                let start = self.pos;
+                let query = self.parse_or()?;
+                if self.pos == start && self.peek_lexeme() == Some(&Lexeme::Close) {
+                    return Err(QueryParseError::new(position, "empty parentheses"));
+                }
+                if self.next().is_none() {
+                    return Err(QueryParseError::new(position, "unclosed parenthesis"));
+                }
+                query
+            }
+            Lexeme::Term(value) => analyze(&value, false, self.schema),
+            Lexeme::Regex(value) => {
+                let ignore_case = self.schema.lowercase;
+                if let Err(e) = build_regex(&value, ignore_case) {
+                    let message = match e {
+                        regex::Error::CompiledTooBig(_) => "regex is too large".to_string(),
+                        // The last line of a syntax error describes it.
+                        e => {
+                            let e = e.to_string();
+                            let description = e.lines().last().unwrap_or_default();
+                            format!(
+                                "invalid regex: {}",
+                                description.trim_start_matches("error: ")
+                            )
+                        }
+                    };
+                    return Err(QueryParseError::new(position, &message));
+                }
+                Some(Query::Token(QueryToken::Regex { value, ignore_case }))
+            }
+            Lexeme::Phrase(value, slop) => match (analyze(&value, true, self.schema), slop) {
+                (Some(Query::Token(QueryToken::Phrase { value, offsets })), Some(slop))
+                    if slop > 0 && value.contains(' ') =>
+                {
+                    Some(Query::Token(QueryToken::Proximity {
+                        value,
+                        offsets,
+                        slop,
+                        ordered: true,
+                    }))
+                }
+                (query, _) => query,
+            },
+            _ => unreachable!("{} does not start an operand", lexeme),
+        })
+    }
+}
+
+/// Analyzes the text of a term or phrase into the terms it was indexed as.
+///
+/// The text is split into terms by the schema's tokenizer, lowercased if the
+/// schema lowercases terms, and stripped of stop words. A term that the
+/// tokenizer splits into several terms becomes a phrase, and a term or phrase
+/// that is only stop words is dropped from the query.
+///
+/// A field term is kept as it is, since it is matched against document paths.
+/// A wildcard term is not split into terms or checked for stop words, since
+/// it matches whole indexed terms; it is only lowercased. A fuzzy term is
+/// analyzed like any other term, but is left as a plain term or phrase if it
+/// does not analyze to a single term.
+fn analyze(value: &str, is_phrase: bool, schema: &Schema) -> Option<Query> {
+    if let Some((field, value)) = value.split_once(':').filter(|_| !is_phrase) {
+        if let (Ok(field), false) = (field.parse::<Field>(), value.is_empty()) {
+            let value = value.to_string();
+            return Some(Query::Token(QueryToken::Field { field, value }));
+        }
+    }
+    if !is_phrase && value.contains(['*', '?']) {
+        let value = if schema.lowercase {
+            value.to_lowercase()
+        } else {
+            value.to_string()
+        };
+        return Some(Query::Token(QueryToken::Wildcard { value }));
+    }
+    if let Some((term, edits)) = value.rsplit_once('~').filter(|_| !is_phrase) {
+        let max_edits = match edits {
+            "" => Some(MAX_EDITS),
+            _ => edits.parse::<usize>().ok(),
+        };
+        let mut terms = schema.analyze(term);
+        if let (Some(max_edits), 1) = (max_edits, terms.len()) {
+            return Some(Query::Token(QueryToken::Fuzzy {
+                value: terms.pop().unwrap().0,
+                max_edits: max_edits.min(MAX_EDITS),
+            }));
+        }
+    }
+    let terms = schema.analyze(value);
+    let token = match terms.len() {
+        0 => return None,
+        1 if !is_phrase => QueryToken::Term {
+            value: terms.into_iter().next().unwrap().0,
+        },
+        // Stop words left out of a phrase leave a gap between its terms.
+        _ => QueryToken::Phrase {
+            offsets: terms.iter().map(|(_, pos)| pos - terms[0].1).collect(),
+            value: terms
+                .into_iter()
+                .map(|(term, _)| term)
+                .collect::<Vec<_>>()
+                .join(" "),
+        },
+    };
+    Some(Query::Token(token))
+}
+
+/// Combines the operands of `NEAR` into a single unordered proximity token.
+///
+/// Repeated terms are only matched once. If an operand is not a plain term,
+/// then the operands are ANDed instead.
+fn near(queries: Vec<Query>, slop: usize) -> Option<Query> {
+    let mut terms: Vec<String> = Vec::new();
+    for query in queries.iter() {
+        match query {
+            Query::Token(QueryToken::Term { value }) => {
+                if !terms.contains(value) {
+                    terms.push(value.clone());
+                }
+            }
+            _ => return collapse(queries, Query::And),
+        }
+    }
+    match terms.len() {
+        // A term near itself is just the term.
+        0 | 1 => queries.into_iter().next(),
+        _ => Some(Query::Token(QueryToken::Proximity {
+            offsets: (0..terms.len()).collect(),
+            value: terms.join(" "),
+            slop,
+            ordered: false,
+        })),
+    }
+}
+
+/// Combines queries with an operator, unless there are fewer than two.
+fn collapse(mut queries: Vec<Query>, op: fn(Vec<Query>) -> Query) -> Option<Query> {
+    match queries.len() {
+        0 => None,
+        1 => queries.pop(),
+        _ => Some(op(queries)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a query with the default schema and returns it as a string.
+    fn parsed(query: &str) -> String {
+        parse_query(query, &Schema::default()).unwrap().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            "Or(And(Term(rust), Term(golang)), And(Term(java), Term(python)))",
+            parsed("rust golang OR java AND python")
+        );
+        assert_eq!(
+            "Or(Term(rust), Term(golang), Term(java))",
+            parsed("rust OR golang OR java")
+        );
+        // Lowercase operators are ordinary terms.
+        assert_eq!(
+            "And(Term(rust), Term(or), Term(java))",
+            parsed("rust or java")
+        );
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            "And(Term(rust), Or(Term(golang), Term(java)))",
+            parsed("rust (golang OR java)")
+        );
+        assert_eq!(
+            "Or(Term(rust), And(Or(Term(golang), Term(java)), Term(python)))",
+            parsed("rust OR ((golang OR java) python)")
+        );
+        assert_eq!("Term(rust)", parsed("((rust))"));
+    }
+
+    #[test]
+    fn exclusion() {
+        assert_eq!("And(Term(hair), Not(Term(steve)))", parsed("hair -steve"));
+        assert_eq!(
+            "And(Term(hair), Not(Phrase(steve long)))",
+            parsed("hair -\"steve long\"")
+        );
+        assert_eq!(
+            "And(Term(hair), Not(Term(steve)))",
+            parsed("hair NOT steve")
+        );
+        assert_eq!("Not(Term(steve))", parsed("NOT steve"));
+        assert_eq!(
+            "Or(Term(rust), Not(Term(java)))",
+            parsed("rust OR NOT java")
+        );
+        // A hyphen inside a word is not an operator.
+        assert_eq!("Phrase(e mail)", parsed("e-mail"));
+    }
+
+    #[test]
+    fn near_joins_plain_terms() {
+        assert_eq!("Near/3(steve hair)", parsed("steve NEAR/3 hair"));
+        assert_eq!("Near/5(steve hair)", parsed("steve NEAR hair"));
+        assert_eq!(
+            "Near/4(steve hair long)",
+            parsed("steve NEAR/2 hair NEAR/4 long")
+        );
+        assert_eq!("Term(steve)", parsed("steve NEAR steve"));
+        assert_eq!(
+            "And(Term(steve), Phrase(the hair))",
+            parsed("steve NEAR \"the hair\"")
+        );
+        assert_eq!("Phrase(steve hair)~2", parsed("\"steve hair\"~2"));
+        assert_eq!("Phrase(steve hair)", parsed("\"steve hair\"~0"));
+    }
+
+    #[test]
+    fn proximity_offsets() {
+        let schema = Schema::default().with_stop_words();
+        let offsets = |query: &str| match parse_query(query, &schema).unwrap() {
+            Query::Token(QueryToken::Proximity { offsets, .. }) => offsets,
+            query => panic!("{} is not a proximity query", query),
+        };
+        // A stop word left out of a phrase leaves a gap, but NEAR has none.
+        assert_eq!(vec![0, 2], offsets("\"steve the hair\"~2"));
+        assert_eq!(vec![0, 1], offsets("steve NEAR the NEAR hair"));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = |query: &str| {
+            parse_query(query, &Schema::default())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            "unclosed phrase at position 9",
+            error("steve OR \"the hair")
+        );
+        assert_eq!(
+            "unclosed parenthesis at position 6",
+            error("steve (hair OR long")
+        );
+        assert_eq!("empty phrase at position 6", error("steve \"\" hair"));
+        assert_eq!("empty phrase at position 6", error("steve \" \" hair"));
+        assert_eq!("empty parentheses at position 6", error("steve () hair"));
+        assert_eq!(
+            "unmatched closing parenthesis at position 5",
+            error("steve) hair")
+        );
+        assert_eq!("OR is missing an operand at position 6", error("steve OR"));
+    }
+}
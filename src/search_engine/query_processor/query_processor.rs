@@ -1,77 +1,386 @@
+use std::io::{Error, ErrorKind, Result};
+
 use crate::{
-    search_engine::crawler::{crawler::Crawler, fs_crawler::FileSystemCrawler},
-    search_engine::indexer::{doc_table::DocTable, mem_index::MemIndex},
+    search_engine::crawler::{
+        crawler::{Changes, Crawler},
+        fs_crawler::FileSystemCrawler,
+    },
+    search_engine::indexer::{
+        doc_index::DocIndex,
+        doc_table::DocTable,
+        file_info::FileInfo,
+        index::{Index, IndexWriter},
+        index_file,
+        mem_index::MemIndex,
+        mmap_index::MmapIndex,
+        schema::Schema,
+        segmented_index::{MergePolicy, SegmentedIndex},
+        stats::{IndexStats, TermStats},
+    },
 };
 
 use super::{
-    intersect::Intersect,
+    bm25::Bm25,
+    explain::Explanation,
+    query_parser::{parse_query, QueryParseError},
     query_result::QueryResult,
-    query_token::{query_to_tokens, QueryToken},
+    scorer::Scorer,
+    search_options::SearchOptions,
+    suggest::suggest,
+    top_k::{disjunctive_terms, max_score, TopK},
 };
 
 /// Processes queries using inverted indices.
-pub struct QueryProcessor {
+///
+/// The query processor is generic over the index backend it searches. By
+/// default the whole index is held in memory (`MemIndex`), but a persisted
+/// index can also be searched in place through a memory map (`MmapIndex`), and
+/// a fast-growing corpus can be kept in a segmented index (`SegmentedIndex`).
+///
+/// Documents and queries are both analyzed with the same `Schema`, which is
+/// fixed when the index is built and stored alongside it. Results are ranked
+/// by a `Scorer`, BM25 unless another one is set with `set_scorer`.
+pub struct QueryProcessor<I: Index = MemIndex> {
     pub(crate) doc_table: DocTable,
-    pub(crate) mem_index: MemIndex,
-    stop_words: bool,
+    pub(crate) index: I,
+    schema: Schema,
+    options: SearchOptions,
+    scorer: Box<dyn Scorer>,
 }
 
-impl QueryProcessor {
+impl QueryProcessor<MemIndex> {
     /// Creates a new query processor.
-    pub fn new(root: &str, stop_words: bool) -> Self {
-        let crawler = FileSystemCrawler::new(root, stop_words);
-        let (doc_table, mem_index) = crawler.crawl().expect("failed to crawl");
+    ///
+    /// Every document under `root` is analyzed with `schema`.
+    pub fn new(root: &str, schema: Schema) -> Self {
+        let crawler = FileSystemCrawler::new(root, &schema);
+        let (doc_table, index) = crawler.crawl().expect("failed to crawl");
         Self {
             doc_table,
-            mem_index,
-            stop_words,
+            index,
+            schema,
+            options: SearchOptions::default(),
+            scorer: Box::new(Bm25::default()),
         }
     }
 
+    /// Opens a query processor over an index previously written by `save`.
+    ///
+    /// No crawling is done: the schema, doc table and inverted index are
+    /// read straight from the index file, and queries are analyzed with the
+    /// schema the index was built with.
+    ///
+    /// # Errors
+    /// * If the index file cannot be read or is not a valid index, then an
+    ///   error is returned.
+    pub fn open(path: &str) -> Result<Self> {
+        let (schema, doc_table, index) = index_file::load(path)?;
+        Ok(Self {
+            doc_table,
+            index,
+            schema,
+            options: SearchOptions::default(),
+            scorer: Box::new(Bm25::default()),
+        })
+    }
+
+    /// Writes the doc table and inverted index to the file at `path`.
+    ///
+    /// The file is replaced atomically, so a failed save leaves the previous
+    /// index in place. The index can later be reopened with
+    /// `QueryProcessor::open`.
+    pub fn save(&self, path: &str) -> Result<()> {
+        index_file::save(path, &self.schema, &self.doc_table, &self.index)
+    }
+}
+
+impl QueryProcessor<SegmentedIndex> {
+    /// Creates a new query processor over a segmented index.
+    ///
+    /// The documents under `root` are added to the index one by one, so they
+    /// are flushed into segments and merged according to `policy`.
+    pub fn new_segmented(root: &str, schema: Schema, policy: MergePolicy) -> Self {
+        let mut qp = Self {
+            doc_table: DocTable::new(),
+            index: SegmentedIndex::new(policy),
+            schema,
+            options: SearchOptions::default(),
+            scorer: Box::new(Bm25::default()),
+        };
+        qp.update(root).expect("failed to crawl");
+        qp
+    }
+}
+
+impl<I: IndexWriter> QueryProcessor<I> {
+    /// Re-crawls `root` and re-indexes only the documents that changed.
+    ///
+    /// Documents that were added, modified or deleted since the index was
+    /// built are patched into the doc table and inverted index in place.
+    ///
+    /// # Errors
+    /// * If the root directory or one of the changed files cannot be read,
+    ///   then an error is returned.
+    pub fn update(&mut self, root: &str) -> Result<Changes> {
+        let crawler = FileSystemCrawler::new(root, &self.schema);
+        crawler.update(&mut self.doc_table, &mut self.index)
+    }
+
+    /// Parses the file at `path` and adds it to the index.
+    ///
+    /// Returns the ID of the new document. The document is searchable as soon
+    /// as this returns.
+    ///
+    /// # Errors
+    /// * If a document with that path is already indexed, then an
+    ///   `AlreadyExists` error is returned; use `reindex_document` instead.
+    /// * If the file cannot be read, then an error is returned.
+    pub fn add_document(&mut self, path: &str) -> Result<usize> {
+        if self.doc_table.get_id(path).is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} is already indexed", path),
+            ));
+        }
+        let info = FileInfo::from_path(path)?;
+        let doc_index = DocIndex::from_file(path, &self.schema)?;
+        let doc_id = self.doc_table.add(path);
+        self.doc_table.set_info(doc_id, info);
+        self.doc_table.set_length(doc_id, doc_index.length());
+        self.index.add(doc_index, doc_id);
+        Ok(doc_id)
+    }
+
+    /// Re-parses the file at `path` and replaces its document in the index.
+    ///
+    /// The old version of the document is deleted and the new one is given a
+    /// fresh ID, which is returned. If the path was not indexed yet, then it
+    /// is simply added.
+    ///
+    /// # Errors
+    /// * If the file cannot be read, then an error is returned and the old
+    ///   version of the document is left in the index.
+    pub fn reindex_document(&mut self, path: &str) -> Result<usize> {
+        let info = FileInfo::from_path(path)?;
+        let doc_index = DocIndex::from_file(path, &self.schema)?;
+        self.delete_document(path);
+        let doc_id = self.doc_table.add(path);
+        self.doc_table.set_info(doc_id, info);
+        self.doc_table.set_length(doc_id, doc_index.length());
+        self.index.add(doc_index, doc_id);
+        Ok(doc_id)
+    }
+
+    /// Deletes the document at `path` from the index.
+    ///
+    /// The document stops matching queries immediately, but its postings are
+    /// only reclaimed by `compact`. Returns whether the document was indexed.
+    pub fn delete_document(&mut self, path: &str) -> bool {
+        match self.doc_table.remove(path) {
+            Some(doc_id) => {
+                self.index.delete(doc_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reclaims the postings of every deleted document.
+    pub fn compact(&mut self) {
+        self.index.compact();
+    }
+}
+
+impl QueryProcessor<MmapIndex> {
+    /// Opens a read-only query processor over a memory-mapped index file.
+    ///
+    /// Unlike `QueryProcessor::open`, the posting lists are not read onto the
+    /// heap; each one is decoded from the mapped file when it is searched.
+    ///
+    /// # Errors
+    /// * If the index file cannot be mapped or is not a valid index, then an
+    ///   error is returned.
+    pub fn open_mmap(path: &str) -> Result<Self> {
+        let (schema, doc_table, index) = MmapIndex::open(path)?;
+        Ok(Self {
+            doc_table,
+            index,
+            schema,
+            options: SearchOptions::default(),
+            scorer: Box::new(Bm25::default()),
+        })
+    }
+}
+
+impl<I: Index> QueryProcessor<I> {
+    /// The schema documents and queries are analyzed with.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The options queries are evaluated with.
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// Changes the options queries are evaluated with.
+    pub fn set_options(&mut self, options: SearchOptions) {
+        self.options = options;
+    }
+
+    /// The scorer results are ranked with.
+    pub fn scorer(&self) -> &dyn Scorer {
+        self.scorer.as_ref()
+    }
+
+    /// Changes the scorer results are ranked with.
+    pub fn set_scorer<S: Scorer + 'static>(&mut self, scorer: S) {
+        self.scorer = Box::new(scorer);
+    }
+
     /// The number of documents in the index.
     pub fn num_docs(&self) -> usize {
         self.doc_table.num_docs()
     }
 
+    /// The ID of the document at `path`, if it is indexed.
+    pub fn doc_id(&self, path: &str) -> Option<usize> {
+        self.doc_table.get_id(path)
+    }
+
     /// The number of terms in the index.
     pub fn num_terms(&self) -> usize {
-        self.mem_index.num_terms()
+        self.index.num_terms()
+    }
+
+    /// Collects statistics about the index: term and posting counts, the
+    /// `top_n` most common terms, document lengths and memory usage.
+    ///
+    /// Every posting list is walked, so this is as slow as reading the whole
+    /// index.
+    pub fn stats(&self, top_n: usize) -> IndexStats {
+        IndexStats::collect(&self.doc_table, &self.index, top_n)
+    }
+
+    /// Returns how often a term occurs in the index.
+    ///
+    /// The term is analyzed with the schema of the index first, as it would
+    /// be in a query. Returns `None` if it is not in the index, or if it does
+    /// not analyze to a single term, like a stop word.
+    pub fn term_stats(&self, term: &str) -> Option<TermStats> {
+        match self.schema.analyze(term).as_slice() {
+            [(term, _)] => self.index.term_stats(term),
+            _ => None,
+        }
     }
 
     /// Searches the index for documents matching the query.
     ///
-    /// Returns a vector of QueryResults sorted by rank that match the given
-    /// query. To match the query, a document must contain all of the terms in
-    /// the query. The rank of a document is the number of terms that it
-    /// contains that are also in the query.
+    /// Returns a vector of QueryResults that match the given query (see
+    /// `parse_query`), best first. The score of a document is the sum of the
+    /// scores the scorer (see `set_scorer`) gives the terms of the query that
+    /// it matches, and documents with the same score are listed in order of
+    /// document ID.
     ///
-    /// For example, if the query is `steve "the hair" hairington`, then the
-    /// search results will contain only documents that contain:
+    /// For example, if the query is `steve ("the hair" OR hairington)`, then
+    /// the search results will contain only documents that contain:
     /// * the term "steve"
-    /// * the phrase "the hair" (the term "the" followed by the term "hair")
-    /// * the term "hairington"
+    /// * the phrase "the hair" (the term "the" followed by the term "hair"),
+    ///   or the term "hairington"
     ///
-    /// A phrase is matched based on the positions of each term in the phrase.
-    pub fn search(&self, query: &str) -> Vec<QueryResult> {
-        let tokens: Vec<QueryToken> = query_to_tokens(query, self.stop_words);
+    /// # Errors
+    /// * If the query is malformed, then a `QueryParseError` pointing at the
+    ///   problem is returned.
+    pub fn search(&self, query: &str) -> std::result::Result<Vec<QueryResult>, QueryParseError> {
+        let query = parse_query(query, &self.schema)?;
+        let mut results = query.search(&self.index, &self.doc_table, self.scorer(), &self.options);
+        // Sort the results by score (highest to lowest) then return them.
+        results.sort_by(|a, b| b.cmp(a));
+        Ok(results)
+    }
 
-        // Search for each token individually.
-        let mut meta_results: Vec<Vec<QueryResult>> = Vec::new();
-        for token in tokens.iter() {
-            meta_results.push(token.search(&self.mem_index, &self.doc_table));
+    /// Searches the index for the best `k` documents matching the query.
+    ///
+    /// The results are the first `k` that `search` would return, but only
+    /// the best `k` are ever kept, in a bounded heap, instead of sorting
+    /// every match. A query that matches any of its terms, like `hair OR
+    /// steve`, is answered without scoring every document that contains
+    /// them, provided the scorer can bound the score of a term (see
+    /// `Scorer::max_term_score`): documents that could not outscore the
+    /// `k`-th best one found so far are skipped.
+    ///
+    /// # Errors
+    /// * If the query is malformed, then a `QueryParseError` pointing at the
+    ///   problem is returned.
+    pub fn search_top_k(
+        &self,
+        query: &str,
+        k: usize,
+    ) -> std::result::Result<Vec<QueryResult>, QueryParseError> {
+        let query = parse_query(query, &self.schema)?;
+        if let Some(terms) = disjunctive_terms(&query) {
+            // Terms that fall back to fuzzy terms are not plain terms.
+            let fuzzy = self.options.fuzzy_fallback
+                && terms.iter().any(|term| self.index.search(term).is_none());
+            if !fuzzy {
+                let scorer = self.scorer();
+                if let Some(results) = max_score(&terms, &self.index, &self.doc_table, scorer, k) {
+                    return Ok(results);
+                }
+            }
+        }
+        let mut top = TopK::new(k);
+        for result in query.search(&self.index, &self.doc_table, self.scorer(), &self.options) {
+            top.push(result);
         }
+        Ok(top.into_sorted_vec())
+    }
+
+    /// Explains the score a document gets for a query, or why it does not
+    /// match.
+    ///
+    /// The explanation is a tree that follows the structure of the query,
+    /// down to the positions of each term in the document, with the score
+    /// every part of the query adds (see `Explanation`). The score at its
+    /// root is the score `search` lists the document with.
+    ///
+    /// # Errors
+    /// * If the query is malformed, then a `QueryParseError` pointing at the
+    ///   problem is returned.
+    pub fn explain(
+        &self,
+        query: &str,
+        doc_id: usize,
+    ) -> std::result::Result<Explanation, QueryParseError> {
+        let query = parse_query(query, &self.schema)?;
+        Ok(query.explain(
+            &self.index,
+            &self.doc_table,
+            self.scorer(),
+            &self.options,
+            doc_id,
+        ))
+    }
 
-        // Intersect the results.
-        let itr = meta_results.iter_mut();
-        if let Some(results) = itr.reduce(|acc, next| {
-            acc.intersect(next);
-            acc
-        }) {
-            // Sort the results by rank (highest to lowest) then return them.
-            results.sort_by(|a, b| b.cmp(a));
-            results.to_vec()
-        } else {
-            Vec::new()
+    /// Suggests corrected versions of a query, most likely first.
+    ///
+    /// Misspelled or rare terms are replaced by close terms that are in many
+    /// documents (see `suggest`), and only suggestions that find at least one
+    /// document are kept. Returns at most `limit` suggestions, or none if no
+    /// term of the query needs correcting.
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<String> {
+        let mut suggestions: Vec<String> = Vec::new();
+        for suggestion in suggest(query, &self.schema, &self.index, self.num_docs()) {
+            if suggestions.len() == limit {
+                break;
+            }
+            let found = self
+                .search(&suggestion)
+                .is_ok_and(|results| !results.is_empty());
+            if found && !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
         }
+        suggestions
     }
 }
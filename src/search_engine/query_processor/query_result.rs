@@ -3,72 +3,106 @@ use std::fmt::{Debug, Display};
 
 use crate::search_engine::indexer::doc_table::DocTable;
 
+use super::scorer::TermMatch;
+
 /// A result of a query.
 ///
-/// Contains the name and id of the document and its rank. The rank is the
-/// number of terms in the query that are also in the document.
-#[derive(Clone, Eq)]
+/// Contains the name and id of the document and its score, along with the
+/// statistics of every term the score was computed from.
+///
+/// Results are ordered by score. Results with the same score are ordered by
+/// document ID, the lower ID being the greater result, so that sorting
+/// results from greatest to least is deterministic.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::query_processor::query_result::QueryResult;
+///
+/// let mut results = vec![
+///     QueryResult::new(2, "b.txt".to_string(), 1.5),
+///     QueryResult::new(1, "a.txt".to_string(), 1.5),
+///     QueryResult::new(3, "c.txt".to_string(), 2.0),
+/// ];
+/// results.sort_by(|a, b| b.cmp(a));
+///
+/// let ids: Vec<usize> = results.iter().map(|r| r.doc_id).collect();
+/// assert_eq!(vec![3, 1, 2], ids);
+/// ```
+#[derive(Clone)]
 pub struct QueryResult {
     pub doc_id: usize,
     pub doc_name: String,
-    pub rank: usize,
+    pub score: f64,
+
+    /// The terms of the query that occur in the document, as they were
+    /// scored; a phrase counts as a single term. Terms of excluded queries
+    /// are not included.
+    pub matches: Vec<TermMatch>,
 }
 
 impl QueryResult {
     /// Creates a new QueryResult.
-    pub fn new(doc_id: usize, doc_name: String, rank: usize) -> Self {
+    pub fn new(doc_id: usize, doc_name: String, score: f64) -> Self {
         Self {
             doc_id,
             doc_name,
-            rank,
+            score,
+            matches: Vec::new(),
         }
     }
 
-    /// Creates a new QueryResult from the docid and the rank.
-    pub fn from(doc_id: usize, postings: &Vec<usize>, doc_table: &DocTable) -> Self {
+    /// Creates a new QueryResult from the docid, its score and the matches
+    /// it was scored from.
+    pub fn from(doc_id: usize, score: f64, matches: Vec<TermMatch>, doc_table: &DocTable) -> Self {
         let maybe_name = doc_table.get_name(doc_id);
         let name = maybe_name.expect("doc_id not found").to_string();
-        let roodrank = postings.len();
         Self {
             doc_id,
             doc_name: name,
-            rank: roodrank,
+            score,
+            matches,
         }
     }
+
+    /// Adds the score and the matches of another result for the same
+    /// document to this one.
+    pub(super) fn merge(&mut self, other: &QueryResult) {
+        self.score += other.score;
+        self.matches.extend_from_slice(&other.matches);
+    }
 }
 
 impl PartialEq for QueryResult {
     fn eq(&self, other: &Self) -> bool {
-        self.rank == other.rank && self.doc_id == other.doc_id
+        self.cmp(other) == Ordering::Equal
     }
 }
 
+impl Eq for QueryResult {}
+
 impl Ord for QueryResult {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.rank.cmp(&other.rank) {
-            Ordering::Equal => self.doc_id.cmp(&other.doc_id),
-            other => other,
-        }
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.doc_id.cmp(&self.doc_id))
     }
 }
 
 impl PartialOrd for QueryResult {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self.rank.cmp(&other.rank) {
-            Ordering::Equal => Some(self.doc_id.cmp(&other.doc_id)),
-            other => Some(other),
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Display for QueryResult {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "[{}] {} {}", self.doc_id, self.doc_name, self.rank)
+        write!(f, "[{}] {} {:.3}", self.doc_id, self.doc_name, self.score)
     }
 }
 
 impl Debug for QueryResult {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "[{}] {} {}", self.doc_id, self.doc_name, self.rank)
+        write!(f, "[{}] {} {:.3}", self.doc_id, self.doc_name, self.score)
     }
 }
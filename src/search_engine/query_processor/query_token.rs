@@ -1,18 +1,70 @@
-use crate::search_engine::filters::stop_words::STOP_WORDS;
 use crate::search_engine::indexer::doc_table::DocTable;
-use crate::search_engine::indexer::mem_index::MemIndex;
+use crate::search_engine::indexer::index::Index;
+use crate::search_engine::indexer::postings::{Cursor, PostingList};
+use crate::search_engine::indexer::schema::Schema;
+use std::borrow::Cow;
 use std::fmt::Error;
 use std::fmt::Formatter;
 use std::fmt::{Debug, Display};
+use std::sync::Arc;
 
+use super::explain::Explanation;
+use super::field::Field;
+use super::fuzzy::{auto_max_edits, fuzzy_terms};
+use super::query_parser::{parse_query, Query, QueryParseError};
 use super::query_result::QueryResult;
+use super::regex_term::{build_regex, literal_prefix};
+use super::scorer::{Scorer, TermMatch};
+use super::search_options::SearchOptions;
+use super::union::Union;
 
-/// A token in a query.
-/// 
-/// A token is a word or phrase in a query.
+/// A token in a query: a term, a phrase, or a term that matches several
+/// indexed terms or documents.
+///
+/// The `offsets` of a phrase or proximity token hold the position of each of
+/// its terms relative to the first, so that a stop word left out of the
+/// query still leaves a gap where it was, as it does in documents.
 pub enum QueryToken {
+    /// A single term.
     Term { value: String },
-    Phrase { value: String },
+
+    /// Terms that occur next to each other, in order, like
+    /// `"steve hairington"`.
+    Phrase { value: String, offsets: Vec<usize> },
+
+    /// A term containing `*` (any number of characters) or `?` (exactly one
+    /// character), like `hair*` or `h?ir`, that matches every indexed term
+    /// that fits the pattern.
+    Wildcard { value: String },
+
+    /// A term followed by `~` and an edit distance, like `hairingtn~2`, that
+    /// matches every indexed term within that many edits of it. The distance
+    /// defaults to 2 and is at most 2.
+    Fuzzy { value: String, max_edits: usize },
+
+    /// A regex between slashes, like `/hair(ed|ing)?/`, that matches every
+    /// indexed term it fully matches, ignoring case if the schema lowercases
+    /// terms. A `/` inside the regex is escaped as `\/`. Unlike other terms,
+    /// a regex is not analyzed.
+    Regex { value: String, ignore_case: bool },
+
+    /// Terms that occur with at most `slop` other terms between them. A
+    /// phrase followed by `~` and a number, like `"steve hairington"~5`, is
+    /// ordered. Terms joined by `NEAR/N`, like `steve NEAR/3 hairington`, are
+    /// not, and a bare `NEAR` allows up to 5. Only plain terms can be joined
+    /// by `NEAR`; anything else on either side of it is simply ANDed.
+    Proximity {
+        value: String,
+        offsets: Vec<usize>,
+        slop: usize,
+        ordered: bool,
+    },
+
+    /// A term starting with `path:`, `name:` or `ext:`, like `path:docs/api`,
+    /// `name:readme` or `ext:md`, that matches documents by their path (see
+    /// `Field`). Combined with other terms, as in `ext:md error`, it restricts
+    /// their results to the matching documents without changing their score.
+    Field { field: Field, value: String },
 }
 
 impl QueryToken {
@@ -20,7 +72,12 @@ impl QueryToken {
     pub fn push(&mut self, c: char) {
         match self {
             QueryToken::Term { value } => value.push(c),
-            QueryToken::Phrase { value } => value.push(c),
+            QueryToken::Phrase { value, .. } => value.push(c),
+            QueryToken::Wildcard { value } => value.push(c),
+            QueryToken::Fuzzy { value, .. } => value.push(c),
+            QueryToken::Regex { value, .. } => value.push(c),
+            QueryToken::Proximity { value, .. } => value.push(c),
+            QueryToken::Field { value, .. } => value.push(c),
         }
     }
 
@@ -28,19 +85,150 @@ impl QueryToken {
     pub fn is_empty(&self) -> bool {
         match self {
             QueryToken::Term { value } => value.is_empty(),
-            QueryToken::Phrase { value } => value.is_empty(),
+            QueryToken::Phrase { value, .. } => value.is_empty(),
+            QueryToken::Wildcard { value } => value.is_empty(),
+            QueryToken::Fuzzy { value, .. } => value.is_empty(),
+            QueryToken::Regex { value, .. } => value.is_empty(),
+            QueryToken::Proximity { value, .. } => value.is_empty(),
+            QueryToken::Field { value, .. } => value.is_empty(),
         }
     }
 
     /// Searches the index for results that match the given token.
-    /// 
+    ///
     /// For a term token, the index is searched for documents that contain
-    /// the term. For a phrase token, the index is searched for documents that
-    /// contain all the terms in the phrase and are in the correct order.
-    pub fn search(&self, index: &MemIndex, docs: &DocTable) -> Vec<QueryResult> {
+    /// the term, and each is scored by `scorer`. For a phrase token, the
+    /// index is searched for documents that contain all the terms in the
+    /// phrase in the correct order, and each is scored as if the phrase were
+    /// a single term that occurs as often as the whole phrase does. For
+    /// a wildcard token, the index is searched for documents that contain any
+    /// of the terms the wildcard matches, up to `options.max_expansions` of
+    /// them. For a fuzzy token, the same is done with the terms within
+    /// `max_edits` edits of the term, and the score a term contributes is
+    /// divided by one more than its distance. Every term is scored with the
+    /// document frequency of the fuzzy term itself, so exact matches rank
+    /// above fuzzy ones. For a regex token, the same is done with the terms
+    /// the regex fully matches, checking at most `options.max_regex_scan`
+    /// terms of the dictionary. For a proximity token, the index is searched for
+    /// documents that contain all of its terms within `slop` positions of
+    /// each other, and the closer they are, the higher the score. For a field
+    /// token, every document whose path matches is returned with a score of
+    /// 0, so that it narrows down the results without changing their order.
+    ///
+    /// If `options.fuzzy_fallback` is set and a term is not in the index at
+    /// all, then it is searched for as a fuzzy term instead.
+    ///
+    /// The results are sorted by document ID.
+    pub fn search<I: Index + ?Sized>(
+        &self,
+        index: &I,
+        docs: &DocTable,
+        scorer: &dyn Scorer,
+        options: &SearchOptions,
+    ) -> Vec<QueryResult> {
         match self {
-            QueryToken::Term { value } => handle_term(value, index, docs),
-            QueryToken::Phrase { value } => handle_phrase(value, index, docs),
+            QueryToken::Term { value } => {
+                if options.fuzzy_fallback && index.search(value).is_none() {
+                    let max_edits = auto_max_edits(value);
+                    let terms = fuzzy_expansion(value, max_edits, index, options);
+                    let doc_freq = fuzzy_doc_freq(value, &terms, index);
+                    return handle_expansion(terms, Some(doc_freq), index, docs, scorer);
+                }
+                handle_term(value, None, index, docs, scorer)
+            }
+            QueryToken::Phrase { value, offsets } => {
+                handle_phrase(value, offsets, index, docs, scorer)
+            }
+            QueryToken::Wildcard { value } => {
+                let terms = wildcard_terms(value, index, options);
+                handle_expansion(terms, None, index, docs, scorer)
+            }
+            QueryToken::Fuzzy { value, max_edits } => {
+                let terms = fuzzy_expansion(value, *max_edits, index, options);
+                let doc_freq = fuzzy_doc_freq(value, &terms, index);
+                handle_expansion(terms, Some(doc_freq), index, docs, scorer)
+            }
+            QueryToken::Regex { value, ignore_case } => {
+                let terms = regex_terms(value, *ignore_case, index, options);
+                handle_expansion(terms, None, index, docs, scorer)
+            }
+            QueryToken::Proximity {
+                value,
+                offsets,
+                slop,
+                ordered,
+            } => {
+                let terms: Vec<&str> = value.split_whitespace().collect();
+                handle_proximity(&terms, offsets, *slop, *ordered, index, docs, scorer)
+            }
+            QueryToken::Field { field, value } => handle_field(*field, value, docs),
+        }
+    }
+
+    /// Explains how the token scores a document.
+    ///
+    /// The document is scored exactly as `search` scores it, and the
+    /// explanation lists where each of the terms behind the score occurs.
+    pub fn explain<I: Index + ?Sized>(
+        &self,
+        index: &I,
+        docs: &DocTable,
+        scorer: &dyn Scorer,
+        options: &SearchOptions,
+        doc_id: usize,
+    ) -> Explanation {
+        let name = self.to_string();
+        match self {
+            QueryToken::Term { value } => {
+                if options.fuzzy_fallback && index.search(value).is_none() {
+                    let max_edits = auto_max_edits(value);
+                    let terms = fuzzy_expansion(value, max_edits, index, options);
+                    let name = format!(
+                        "{}, not in the index, as Fuzzy({}~{})",
+                        name, value, max_edits
+                    );
+                    let doc_freq = Some(fuzzy_doc_freq(value, &terms, index));
+                    return explain_expansion(name, terms, doc_freq, index, docs, scorer, doc_id);
+                }
+                explain_term(value, None, index, docs, scorer, doc_id)
+            }
+            QueryToken::Phrase { value, offsets } => {
+                explain_phrase(name, value, offsets, index, docs, scorer, doc_id)
+            }
+            QueryToken::Wildcard { value } => {
+                let terms = wildcard_terms(value, index, options);
+                explain_expansion(name, terms, None, index, docs, scorer, doc_id)
+            }
+            QueryToken::Fuzzy { value, max_edits } => {
+                let terms = fuzzy_expansion(value, *max_edits, index, options);
+                let doc_freq = Some(fuzzy_doc_freq(value, &terms, index));
+                explain_expansion(name, terms, doc_freq, index, docs, scorer, doc_id)
+            }
+            QueryToken::Regex { value, ignore_case } => {
+                let terms = regex_terms(value, *ignore_case, index, options);
+                explain_expansion(name, terms, None, index, docs, scorer, doc_id)
+            }
+            QueryToken::Proximity {
+                value,
+                offsets,
+                slop,
+                ordered,
+            } => {
+                let terms: Vec<&str> = value.split_whitespace().collect();
+                let (slop, ordered) = (*slop, *ordered);
+                explain_proximity(
+                    name, &terms, offsets, slop, ordered, index, docs, scorer, doc_id,
+                )
+            }
+            QueryToken::Field { field, value } => match docs.get_name(doc_id) {
+                Some(path) if field.matches(value, path) => {
+                    Explanation::matched(0.0, format!("{}: {} matches", name, path), Vec::new())
+                }
+                Some(path) => {
+                    Explanation::unmatched(format!("{}: {} does not match", name, path), Vec::new())
+                }
+                None => Explanation::unmatched(format!("{}: no such document", name), Vec::new()),
+            },
         }
     }
 }
@@ -49,7 +237,18 @@ impl Display for QueryToken {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             QueryToken::Term { value } => write!(f, "Term({})", value),
-            QueryToken::Phrase { value } => write!(f, "Phrase({})", value),
+            QueryToken::Phrase { value, .. } => write!(f, "Phrase({})", value),
+            QueryToken::Wildcard { value } => write!(f, "Wildcard({})", value),
+            QueryToken::Fuzzy { value, max_edits } => write!(f, "Fuzzy({}~{})", value, max_edits),
+            QueryToken::Regex { value, .. } => write!(f, "Regex({})", value),
+            QueryToken::Proximity {
+                value,
+                slop,
+                ordered: true,
+                ..
+            } => write!(f, "Phrase({})~{}", value, slop),
+            QueryToken::Proximity { value, slop, .. } => write!(f, "Near/{}({})", slop, value),
+            QueryToken::Field { field, value } => write!(f, "Field({}:{})", field, value),
         }
     }
 }
@@ -58,7 +257,18 @@ impl Debug for QueryToken {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             QueryToken::Term { value } => write!(f, "Term({})", value),
-            QueryToken::Phrase { value } => write!(f, "Phrase({})", value),
+            QueryToken::Phrase { value, .. } => write!(f, "Phrase({})", value),
+            QueryToken::Wildcard { value } => write!(f, "Wildcard({})", value),
+            QueryToken::Fuzzy { value, max_edits } => write!(f, "Fuzzy({}~{})", value, max_edits),
+            QueryToken::Regex { value, .. } => write!(f, "Regex({})", value),
+            QueryToken::Proximity {
+                value,
+                slop,
+                ordered: true,
+                ..
+            } => write!(f, "Phrase({})~{}", value, slop),
+            QueryToken::Proximity { value, slop, .. } => write!(f, "Near/{}({})", slop, value),
+            QueryToken::Field { field, value } => write!(f, "Field({}:{})", field, value),
         }
     }
 }
@@ -70,8 +280,67 @@ impl PartialEq for QueryToken {
                 QueryToken::Term { value: other_val } => my_val == other_val,
                 _ => false,
             },
-            QueryToken::Phrase { value: my_val } => match other {
-                QueryToken::Phrase { value: other_val } => my_val == other_val,
+            QueryToken::Phrase {
+                value: my_val,
+                offsets: my_offsets,
+            } => match other {
+                QueryToken::Phrase {
+                    value: other_val,
+                    offsets: other_offsets,
+                } => my_val == other_val && my_offsets == other_offsets,
+                _ => false,
+            },
+            QueryToken::Wildcard { value: my_val } => match other {
+                QueryToken::Wildcard { value: other_val } => my_val == other_val,
+                _ => false,
+            },
+            QueryToken::Fuzzy {
+                value: my_val,
+                max_edits: my_edits,
+            } => match other {
+                QueryToken::Fuzzy {
+                    value: other_val,
+                    max_edits: other_edits,
+                } => my_val == other_val && my_edits == other_edits,
+                _ => false,
+            },
+            QueryToken::Regex {
+                value: my_val,
+                ignore_case: my_case,
+            } => match other {
+                QueryToken::Regex {
+                    value: other_val,
+                    ignore_case: other_case,
+                } => my_val == other_val && my_case == other_case,
+                _ => false,
+            },
+            QueryToken::Proximity {
+                value: my_val,
+                offsets: my_offsets,
+                slop: my_slop,
+                ordered: my_ordered,
+            } => match other {
+                QueryToken::Proximity {
+                    value: other_val,
+                    offsets: other_offsets,
+                    slop: other_slop,
+                    ordered: other_ordered,
+                } => {
+                    my_val == other_val
+                        && my_offsets == other_offsets
+                        && my_slop == other_slop
+                        && my_ordered == other_ordered
+                }
+                _ => false,
+            },
+            QueryToken::Field {
+                field: my_field,
+                value: my_val,
+            } => match other {
+                QueryToken::Field {
+                    field: other_field,
+                    value: other_val,
+                } => my_field == other_field && my_val == other_val,
                 _ => false,
             },
         }
@@ -80,6 +349,9 @@ impl PartialEq for QueryToken {
 
 /// Parse the incoming query string and returns a vector of `QueryToken`.
 ///
+/// The tokens are the terms and phrases of the query, in order, as parsed by
+/// `parse_query`; the operators and grouping that combine them are dropped.
+///
 /// For example, if the query is `steve "the hair" hairington`, then the
 /// search results will contain only documents that contain:
 ///
@@ -91,145 +363,965 @@ impl PartialEq for QueryToken {
 /// ]
 /// ```
 ///
-/// If the stop words filter is enabled, then stop words will be removed
-/// from the query.
+/// Every term and phrase is analyzed with `schema` (see `parse_query`).
+///
+/// # Errors
+/// * If the query is malformed, then the `QueryParseError` from `parse_query`
+///   is returned.
 ///
 /// # Examples
 ///
 /// ```
+/// use roogle::search_engine::indexer::schema::Schema;
 /// use roogle::search_engine::query_processor::query_token::query_to_tokens;
 /// use roogle::search_engine::query_processor::query_token::QueryToken;
 ///
-/// let tokens = query_to_tokens("steve \"the hair\" hairington", false);
+/// let tokens = query_to_tokens("steve \"the hair\" hairington", &Schema::default()).unwrap();
 /// let mut iter = tokens.iter();
 ///
 /// assert_eq!("Term(steve)".to_string(), iter.next().unwrap().to_string());
 /// assert_eq!("Phrase(the hair)".to_string(), iter.next().unwrap().to_string());
 /// assert_eq!("Term(hairington)".to_string(), iter.next().unwrap().to_string());
 /// ```
-pub fn query_to_tokens(query: &str, stop_words: bool) -> Vec<QueryToken> {
-    let mut query: String = query.trim().to_lowercase();
-    let mut tokens: Vec<QueryToken> = Vec::new();
-    let mut token: QueryToken = QueryToken::Term {
-        value: String::new(),
+pub fn query_to_tokens(query: &str, schema: &Schema) -> Result<Vec<QueryToken>, QueryParseError> {
+    parse_query(query, schema).map(Query::into_tokens)
+}
+
+/// Handles search for a single term.
+///
+/// If `doc_freq` is given, the term is scored as if it occurred in that many
+/// documents.
+fn handle_term<I: Index + ?Sized>(
+    term: &str,
+    doc_freq: Option<usize>,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+) -> Vec<QueryResult> {
+    let mut query_results: Vec<QueryResult> = Vec::new();
+    if let Some(results) = index.search(term) {
+        let term: Arc<str> = term.into();
+        let doc_freq = doc_freq.unwrap_or_else(|| self::doc_freq(&results, index));
+        results
+            .iter()
+            .filter(|(doc_id, _)| !index.is_deleted(*doc_id))
+            .for_each(|(doc_id, positions)| {
+                let term = TermMatch::new(&term, positions.len(), doc_freq, doc_id, docs);
+                let score = scorer.score_term(&term);
+                query_results.push(QueryResult::from(doc_id, score, vec![term], docs));
+            });
+    }
+    query_results
+}
+
+/// Returns the number of documents a term occurs in, and its positions in a
+/// document, if it occurs there.
+fn term_positions<I: Index + ?Sized>(
+    term: &str,
+    index: &I,
+    doc_id: usize,
+) -> (usize, Option<Vec<usize>>) {
+    let postings = match index.search(term) {
+        Some(postings) => postings,
+        None => return (0, None),
     };
-    if stop_words {
-        query = remove_stop_words(&query);
-    }
-    for c in query.chars() {
-        if c == '"' {
-            // Toggle between "term" and "phrase" tokens.
-            match token {
-                QueryToken::Term { value: _ } => {
-                    token = QueryToken::Phrase {
-                        value: String::new(),
-                    };
-                }
-                QueryToken::Phrase { value: _ } => {
-                    // Add the phrase to the list of tokens.
-                    tokens.push(token);
-                    token = QueryToken::Term {
-                        value: String::new(),
-                    };
-                }
-            }
-        } else if c == ' ' {
-            match token {
-                QueryToken::Term {
-                    value: ref mut term,
-                } if term.len() > 0 => {
-                    // Add the term to the list of tokens.
-                    tokens.push(token);
-                    token = QueryToken::Term {
-                        value: String::new(),
-                    };
-                }
-                QueryToken::Phrase { value: _ } => {
-                    // Add the space to the phrase.
-                    token.push(c);
-                }
-                _ => {}
-            }
-        } else {
-            // Add the current character to the current token.
-            token.push(c);
-        }
+    let doc_freq = doc_freq(&postings, index);
+    if index.is_deleted(doc_id) {
+        return (doc_freq, None);
     }
-    // If there's a last token, add it to the token vector.
-    if !token.is_empty() {
-        tokens.push(token);
+    (doc_freq, postings.get(doc_id).map(Iterator::collect))
+}
+
+/// Returns the number of documents in a posting list that are not deleted.
+pub(super) fn doc_freq<I: Index + ?Sized>(postings: &PostingList, index: &I) -> usize {
+    postings
+        .iter()
+        .filter(|(doc_id, _)| !index.is_deleted(*doc_id))
+        .count()
+}
+
+/// Explains the score of a term or phrase, given the number of documents it
+/// occurs in and its positions in a document.
+fn describe_match(
+    name: String,
+    term: &str,
+    doc_freq: usize,
+    positions: Option<&[usize]>,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    doc_id: usize,
+) -> Explanation {
+    let positions = match positions {
+        Some(positions) => positions,
+        None => return Explanation::unmatched(format!("{}: does not occur", name), Vec::new()),
+    };
+    // Long lists of positions are cut short.
+    let mut shown: Vec<String> = positions.iter().take(10).map(usize::to_string).collect();
+    if positions.len() > shown.len() {
+        shown.push("...".to_string());
     }
-    tokens
+    let occurs = match positions.len() {
+        1 => format!("occurs once, at position {}", shown[0]),
+        n => format!("occurs {} times, at positions {}", n, shown.join(", ")),
+    };
+    let term = TermMatch::new(&term.into(), positions.len(), doc_freq, doc_id, docs);
+    let description = format!(
+        "{}: {}; in {} of {} documents; document length {} against an average of {:.1}",
+        name, occurs, term.doc_freq, term.num_docs, term.doc_len, term.avg_doc_len
+    );
+    Explanation::matched(scorer.score_term(&term), description, Vec::new())
+}
+
+/// Explains the score of a single term in a document, as `handle_term`
+/// computes it.
+fn explain_term<I: Index + ?Sized>(
+    term: &str,
+    doc_freq: Option<usize>,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    doc_id: usize,
+) -> Explanation {
+    let (term_doc_freq, positions) = term_positions(term, index, doc_id);
+    let doc_freq = doc_freq.unwrap_or(term_doc_freq);
+    let name = format!("Term({})", term);
+    describe_match(
+        name,
+        term,
+        doc_freq,
+        positions.as_deref(),
+        docs,
+        scorer,
+        doc_id,
+    )
 }
 
-/// Removes stop words from the query.
-fn remove_stop_words(query: &String) -> String {
-    let mut new_query = String::new();
-    let mut words = query.split_whitespace();
-    while let Some(word) = words.next() {
-        if !STOP_WORDS.contains(&word) {
-            new_query.push_str(word);
-            new_query.push(' ');
+/// Explains the score of a phrase, as `handle_phrase` computes it.
+fn explain_phrase<I: Index + ?Sized>(
+    name: String,
+    phrase: &str,
+    offsets: &[usize],
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    doc_id: usize,
+) -> Explanation {
+    let terms: Vec<&str> = phrase.split_whitespace().collect();
+    let positions: Option<Vec<Vec<usize>>> = terms
+        .iter()
+        .map(|term| term_positions(term, index, doc_id).1)
+        .collect();
+    let starts = positions
+        .map(|positions| phrase_starts(&positions, offsets))
+        .filter(|starts| !starts.is_empty());
+    match starts {
+        Some(starts) => {
+            let doc_freq = phrase_freqs(&terms, offsets, index).len();
+            let phrase = terms.join(" ");
+            describe_match(name, &phrase, doc_freq, Some(&starts), docs, scorer, doc_id)
+        }
+        None => {
+            // Show which of the terms occur, to tell why the phrase does not.
+            let details = terms
+                .iter()
+                .map(|term| explain_term(term, None, index, docs, scorer, doc_id))
+                .collect();
+            Explanation::unmatched(format!("{}: does not occur", name), details)
         }
     }
-    new_query.pop(); // Removes the trailing space.
-    new_query
 }
 
-/// Handles search for a single term.
-fn handle_term(term: &str, index: &MemIndex, docs: &DocTable) -> Vec<QueryResult> {
+/// Explains the score of the terms a wildcard, fuzzy or regex term expands
+/// to, as `handle_expansion` computes it.
+fn explain_expansion<I: Index + ?Sized>(
+    name: String,
+    terms: Vec<(&str, usize)>,
+    doc_freq: Option<usize>,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    doc_id: usize,
+) -> Explanation {
+    let num_terms = terms.len();
+    let mut details: Vec<Explanation> = Vec::new();
+    for (term, distance) in terms.into_iter() {
+        let mut detail = explain_term(term, doc_freq, index, docs, scorer, doc_id);
+        if !detail.matched {
+            continue;
+        }
+        if distance > 0 {
+            detail.score /= (1 + distance) as f64;
+            detail.description += &format!(
+                ", divided by {} for being {} edits away",
+                1 + distance,
+                distance
+            );
+        }
+        details.push(detail);
+    }
+    if num_terms == 0 {
+        let description = format!("{}: matches no terms in the index", name);
+        return Explanation::unmatched(description, details);
+    }
+    if details.is_empty() {
+        let description = format!("{}: none of the {} terms it matches occur", name, num_terms);
+        return Explanation::unmatched(description, details);
+    }
+    let score = details.iter().map(|detail| detail.score).sum();
+    let description = format!(
+        "{}: {} of the {} terms it matches occur, so their scores are added",
+        name,
+        details.len(),
+        num_terms
+    );
+    Explanation::matched(score, description, details)
+}
+
+/// Explains the score of terms that must occur near each other, as
+/// `handle_proximity` computes it.
+#[allow(clippy::too_many_arguments)]
+fn explain_proximity<I: Index + ?Sized>(
+    name: String,
+    terms: &[&str],
+    offsets: &[usize],
+    slop: usize,
+    ordered: bool,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    doc_id: usize,
+) -> Explanation {
+    let (doc_freqs, positions): (Vec<usize>, Vec<Option<Vec<usize>>>) = terms
+        .iter()
+        .map(|term| term_positions(term, index, doc_id))
+        .unzip();
+    let details: Vec<Explanation> = terms
+        .iter()
+        .zip(doc_freqs.iter())
+        .zip(positions.iter())
+        .map(|((term, &doc_freq), positions)| {
+            let name = format!("Term({})", term);
+            describe_match(
+                name,
+                term,
+                doc_freq,
+                positions.as_deref(),
+                docs,
+                scorer,
+                doc_id,
+            )
+        })
+        .collect();
+    let positions: Vec<Vec<usize>> = match positions.into_iter().collect() {
+        Some(positions) => positions,
+        None => {
+            let description = format!("{}: not every term occurs", name);
+            return Explanation::unmatched(description, details);
+        }
+    };
+
+    let order = if ordered { " in order" } else { "" };
+    let window =
+        closest_match(&positions, offsets, ordered).filter(|&window| gap(window, offsets) <= slop);
+    let (start, end) = match window {
+        Some(window) => window,
+        None => {
+            let description = format!(
+                "{}: the terms never occur{} with at most {} other positions between them",
+                name, order, slop
+            );
+            return Explanation::unmatched(description, details);
+        }
+    };
+    let gap = gap((start, end), offsets);
+    let description = if slop == 0 {
+        format!(
+            "{}: first occurs at positions {} to {}, scored by adding the scores of its terms",
+            name, start, end
+        )
+    } else {
+        format!(
+            "{}: closest match{} at positions {} to {}, {} apart with a slop of {}, scored from the scores of its terms",
+            name, order, start, end, gap, slop
+        )
+    };
+    let score = details.iter().map(|detail| detail.score).sum();
+    Explanation::matched(
+        scorer.score_proximity(score, slop, gap),
+        description,
+        details,
+    )
+}
+
+/// Handles search for documents by their path.
+fn handle_field(field: Field, value: &str, docs: &DocTable) -> Vec<QueryResult> {
+    let mut ids: Vec<usize> = docs
+        .ids()
+        .filter(|&id| field.matches(value, docs.get_name(id).unwrap()))
+        .collect();
+    ids.sort_unstable();
+    ids.into_iter()
+        .map(|id| QueryResult::from(id, 0.0, Vec::new(), docs))
+        .collect()
+}
+
+/// Handles search for the terms a wildcard, fuzzy or regex term expands to.
+///
+/// Each term comes with its edit distance, and the score it contributes is
+/// divided by one more than that. If `doc_freq` is given, every term is scored
+/// as if it occurred in that many documents. The results of every term are
+/// unioned.
+fn handle_expansion<I: Index + ?Sized>(
+    terms: Vec<(&str, usize)>,
+    doc_freq: Option<usize>,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+) -> Vec<QueryResult> {
     let mut query_results: Vec<QueryResult> = Vec::new();
-    if let Some(results) = index.search(term) {
-        results.iter().for_each(|(doc_id, postings)| {
-            query_results.push(QueryResult::from(*doc_id, postings, docs));
-        });
+    for (term, distance) in terms.into_iter() {
+        let mut results = handle_term(term, doc_freq, index, docs, scorer);
+        for result in results.iter_mut() {
+            result.score /= (1 + distance) as f64;
+        }
+        query_results.union(&results);
     }
     query_results
 }
 
+/// Returns the terms a wildcard term expands to.
+///
+/// `*` matches any number of characters and `?` matches exactly one. Only the
+/// terms starting with the literal prefix of the pattern are scanned.
+fn wildcard_terms<'a, I: Index + ?Sized>(
+    pattern: &str,
+    index: &'a I,
+    options: &SearchOptions,
+) -> Vec<(&'a str, usize)> {
+    let prefix_len = pattern.find(['*', '?']).unwrap_or(pattern.len());
+    let mut terms: Vec<(&str, usize)> = index
+        .terms_with_prefix(&pattern[..prefix_len])
+        .filter(|term| wildcard_match(pattern, term))
+        .map(|term| (term, 0))
+        .take(options.max_expansions.saturating_add(1))
+        .collect();
+    if terms.len() > options.max_expansions {
+        log::warn!(
+            "{:?} matches more than {} terms; ignoring the rest",
+            pattern,
+            options.max_expansions
+        );
+        terms.truncate(options.max_expansions);
+    }
+    terms
+}
+
+/// Returns the terms a fuzzy term expands to, with their distance.
+///
+/// The terms within `max_edits` edits of the term are taken closest first, up
+/// to `options.max_expansions` of them.
+fn fuzzy_expansion<'a, I: Index + ?Sized>(
+    term: &str,
+    max_edits: usize,
+    index: &'a I,
+    options: &SearchOptions,
+) -> Vec<(&'a str, usize)> {
+    let mut matches = fuzzy_terms(index, term, max_edits);
+    matches.truncate(options.max_expansions);
+    matches
+}
+
+/// Returns the number of documents every term a fuzzy term expands to is
+/// scored as occurring in.
+///
+/// This is the document frequency of the fuzzy term itself, so that a rare
+/// misspelling does not outscore the common term that was asked for. If the
+/// fuzzy term is not in any document, the most common term it expands to is
+/// used instead.
+fn fuzzy_doc_freq<I: Index + ?Sized>(term: &str, terms: &[(&str, usize)], index: &I) -> usize {
+    let term_doc_freq = |term: &str| {
+        index
+            .search(term)
+            .map_or(0, |postings| doc_freq(&postings, index))
+    };
+    match term_doc_freq(term) {
+        0 => terms
+            .iter()
+            .map(|(term, _)| term_doc_freq(term))
+            .max()
+            .unwrap_or(0),
+        doc_freq => doc_freq,
+    }
+}
+
+/// Returns the terms a regex term expands to.
+///
+/// Only the terms starting with the literal prefix of the regex are scanned,
+/// up to `options.max_regex_scan` of them.
+fn regex_terms<'a, I: Index + ?Sized>(
+    pattern: &str,
+    ignore_case: bool,
+    index: &'a I,
+    options: &SearchOptions,
+) -> Vec<(&'a str, usize)> {
+    // The pattern was already checked when the query was parsed.
+    let regex = match build_regex(pattern, ignore_case) {
+        Ok(regex) => regex,
+        Err(_) => return Vec::new(),
+    };
+    let mut prefix = literal_prefix(pattern);
+    if ignore_case {
+        prefix = prefix.to_lowercase();
+    }
+
+    let mut terms: Vec<(&str, usize)> = Vec::new();
+    for (scanned, term) in index.terms_with_prefix(&prefix).enumerate() {
+        if scanned == options.max_regex_scan {
+            log::warn!(
+                "/{}/ would scan more than {} terms; ignoring the rest",
+                pattern,
+                options.max_regex_scan
+            );
+            break;
+        }
+        if regex.is_match(term) {
+            if terms.len() == options.max_expansions {
+                log::warn!(
+                    "/{}/ matches more than {} terms; ignoring the rest",
+                    pattern,
+                    options.max_expansions
+                );
+                break;
+            }
+            terms.push((term, 0));
+        }
+    }
+    terms
+}
+
+/// Returns whether a term matches a wildcard pattern.
+pub(super) fn wildcard_match(pattern: &str, term: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let term: Vec<char> = term.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` seen, and of the term where it was seen.
+    let mut star: Option<(usize, usize)> = None;
+    while t < term.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == term[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character and retry.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Handles search for a phrase.
-fn handle_phrase(phrase: &str, index: &MemIndex, docs: &DocTable) -> Vec<QueryResult> {
+///
+/// A phrase is scored as if it were a single term: its frequency in a
+/// document is the number of times the whole phrase occurs there, and its
+/// document frequency is the number of documents it occurs in.
+fn handle_phrase<I: Index + ?Sized>(
+    phrase: &str,
+    offsets: &[usize],
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+) -> Vec<QueryResult> {
+    let terms: Vec<&str> = phrase.split_whitespace().collect();
+    let freqs = phrase_freqs(&terms, offsets, index);
+    let phrase: Arc<str> = terms.join(" ").into();
+    let doc_freq = freqs.len();
+    freqs
+        .into_iter()
+        .map(|(doc_id, freq)| {
+            let term = TermMatch::new(&phrase, freq, doc_freq, doc_id, docs);
+            QueryResult::from(doc_id, scorer.score_term(&term), vec![term], docs)
+        })
+        .collect()
+}
+
+/// Returns the documents a phrase occurs in, with the number of times it
+/// occurs in each, sorted by document ID.
+fn phrase_freqs<I: Index + ?Sized>(
+    terms: &[&str],
+    offsets: &[usize],
+    index: &I,
+) -> Vec<(usize, usize)> {
+    let mut freqs: Vec<(usize, usize)> = Vec::new();
+    for_each_co_occurrence(terms, index, |doc_id, positions, _| {
+        let freq = phrase_starts(positions, offsets).len();
+        if freq > 0 {
+            freqs.push((doc_id, freq));
+        }
+    });
+    freqs
+}
+
+/// Returns the position every occurrence of a phrase starts at.
+///
+/// `positions[i]` holds the sorted positions of the i-th term of the phrase,
+/// which must occur `offsets[i]` positions after the first.
+fn phrase_starts(positions: &[Vec<usize>], offsets: &[usize]) -> Vec<usize> {
+    positions[0]
+        .iter()
+        .copied()
+        .filter(|&start| {
+            positions[1..]
+                .iter()
+                .zip(offsets[1..].iter())
+                .all(|(next, offset)| next.binary_search(&(start + offset)).is_ok())
+        })
+        .collect()
+}
+
+/// Handles search for terms that occur near each other.
+///
+/// A document matches if it contains every term with at most `slop` other
+/// positions between them, besides the gaps `offsets` leaves for stop words;
+/// if `ordered` is set, the terms must also occur in the order they are
+/// given.
+///
+/// The scores of the terms in a document are added up and passed to
+/// `Scorer::score_proximity` along with the gap of its closest match.
+fn handle_proximity<I: Index + ?Sized>(
+    terms: &[&str],
+    offsets: &[usize],
+    slop: usize,
+    ordered: bool,
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+) -> Vec<QueryResult> {
     let mut query_results: Vec<QueryResult> = Vec::new();
-    let mut terms = phrase.split_whitespace().into_iter();
-    let first_term = terms.next().unwrap();
+    let names: Vec<Arc<str>> = terms.iter().map(|&term| term.into()).collect();
+    let mut doc_freqs: Option<Vec<usize>> = None;
+    for_each_co_occurrence(terms, index, |doc_id, positions, postings| {
+        let gap = closest_match(positions, offsets, ordered).map(|window| gap(window, offsets));
+        if let Some(gap) = gap.filter(|&gap| gap <= slop) {
+            // Counted once, the first time a document matches.
+            let doc_freqs = doc_freqs
+                .get_or_insert_with(|| postings.iter().map(|p| doc_freq(p, index)).collect());
+            let matches: Vec<TermMatch> = names
+                .iter()
+                .zip(positions.iter().zip(doc_freqs.iter()))
+                .map(|(term, (positions, &doc_freq))| {
+                    TermMatch::new(term, positions.len(), doc_freq, doc_id, docs)
+                })
+                .collect();
+            let score = matches.iter().map(|term| scorer.score_term(term)).sum();
+            let score = scorer.score_proximity(score, slop, gap);
+            query_results.push(QueryResult::from(doc_id, score, matches, docs));
+        }
+    });
+    query_results
+}
+
+/// Calls `f` for every document that contains all of the terms, in order of
+/// document ID, with the positions of each term in it and the posting lists
+/// of the terms.
+fn for_each_co_occurrence<I, F>(terms: &[&str], index: &I, mut f: F)
+where
+    I: Index + ?Sized,
+    F: FnMut(usize, &[Vec<usize>], &[Cow<PostingList>]),
+{
+    // Look up the postings of every term once, up front.
+    let postings: Vec<Cow<PostingList>> = match terms.iter().map(|t| index.search(t)).collect() {
+        Some(postings) => postings,
+        None => return,
+    };
+    let (first, rest) = match postings.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut cursors: Vec<Cursor> = rest.iter().map(|p| p.cursor()).collect();
 
     // Iterate over all the documents that contain the first term.
-    if let Some(search_results) = index.search(first_term) {
-        'outer: for (doc_id, doc_positions) in search_results {
-            // Iterate over the positions of the first term in this document
-            let mut pos_iter = doc_positions.iter();
-            let mut rank = doc_positions.len();
-            let mut prev_term = first_term;
-            'mid: while let Some(mut pos) = pos_iter.next().map(|off| *off) {
-                // reset the iterator
-                terms = phrase.split_whitespace().into_iter();
-                terms.next(); // eat the first term
-
-                for next_term in terms.by_ref() {
-                    // Get the positions of the next term in this document.
-                    if let Some(sr) = index.search(next_term) {
-                        if let Some(dp) = sr.get(&doc_id) {
-                            let offset = pos + prev_term.len() + 1;
-                            if dp.contains(&offset) {
-                                rank += dp.len();
-                                pos = offset;
-                                prev_term = next_term;
-                                continue;
-                            }
-                            continue 'mid;
-                        }
-                    }
-                    // If we get here, then the phrase is not in this document.
-                    continue 'mid;
-                }
-                // We matched all the terms in the phrase!
-                let maybe_name = docs.get_name(*doc_id);
-                let name = maybe_name.expect("doc_id not found").to_string();
-                let qr = QueryResult::new(*doc_id, name, rank);
-                query_results.push(qr);
-                continue 'outer;
+    'outer: for (doc_id, first_positions) in first.iter() {
+        if index.is_deleted(doc_id) {
+            continue;
+        }
+        // Find the positions of the other terms in this document. Since
+        // every posting list is sorted by document ID, each cursor only ever
+        // moves forward.
+        let mut positions: Vec<Vec<usize>> = vec![first_positions.collect()];
+        for cursor in cursors.iter_mut() {
+            match cursor.positions_in(doc_id) {
+                Some(p) => positions.push(p.collect()),
+                None => continue 'outer,
             }
         }
+        f(doc_id, &positions, &postings);
+    }
+}
+
+/// Returns the first and last position of the closest occurrence of every
+/// term, in order if `ordered` is set.
+///
+/// `positions[i]` holds the sorted positions of the i-th term, and
+/// `offsets[i]` its position relative to the first term when the terms occur
+/// as a phrase. Returns `None` if the terms never occur together (in order).
+fn closest_match(
+    positions: &[Vec<usize>],
+    offsets: &[usize],
+    ordered: bool,
+) -> Option<(usize, usize)> {
+    if ordered {
+        ordered_match(positions, offsets)
+    } else {
+        unordered_match(positions)
+    }
+}
+
+/// Returns how many positions terms spanning `window` are away from
+/// occurring as a phrase whose terms are at `offsets`. A gap of 0 means the
+/// terms occur as a phrase.
+fn gap(window: (usize, usize), offsets: &[usize]) -> usize {
+    (window.1 - window.0).abs_diff(offsets[offsets.len() - 1])
+}
+
+/// Returns the closest occurrence of terms in order.
+fn ordered_match(positions: &[Vec<usize>], offsets: &[usize]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    'start: for &start in positions[0].iter() {
+        // Follow each term with the closest occurrence of the next one.
+        let mut end = start;
+        for next in positions[1..].iter() {
+            match next.get(next.partition_point(|&pos| pos <= end)) {
+                Some(&pos) => end = pos,
+                // No later start can be followed by this term either.
+                None => break 'start,
+            }
+        }
+        if best.is_none_or(|window| gap((start, end), offsets) < gap(window, offsets)) {
+            best = Some((start, end));
+        }
+        if gap((start, end), offsets) == 0 {
+            break;
+        }
+    }
+    best
+}
+
+/// Returns the closest occurrence of terms in any order.
+///
+/// The smallest window holding an occurrence of every term is found by
+/// sliding a window over the occurrences of all the terms, in order of
+/// position.
+fn unordered_match(positions: &[Vec<usize>]) -> Option<(usize, usize)> {
+    let mut occurrences: Vec<(usize, usize)> = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(term, positions)| positions.iter().map(move |&pos| (pos, term)))
+        .collect();
+    occurrences.sort_unstable();
+
+    let mut counts = vec![0; positions.len()];
+    let mut missing = positions.len();
+    let mut best: Option<(usize, usize)> = None;
+    let mut start = 0;
+    for &(end_pos, term) in occurrences.iter() {
+        if counts[term] == 0 {
+            missing -= 1;
+        }
+        counts[term] += 1;
+        // Shrink the window from the left while it still holds every term.
+        while missing == 0 {
+            let (start_pos, start_term) = occurrences[start];
+            if best.is_none_or(|(s, e)| end_pos - start_pos < e - s) {
+                best = Some((start_pos, end_pos));
+            }
+            counts[start_term] -= 1;
+            if counts[start_term] == 0 {
+                missing += 1;
+            }
+            start += 1;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::indexer::doc_index::DocIndex;
+    use crate::search_engine::indexer::index::IndexWriter;
+    use crate::search_engine::indexer::mem_index::MemIndex;
+    use crate::search_engine::query_processor::bm25::Bm25;
+
+    /// Builds an index of documents, each a name and its text.
+    fn build(documents: &[(String, &str)], schema: &Schema) -> (DocTable, MemIndex) {
+        let mut docs = DocTable::new();
+        let mut index = MemIndex::new();
+        for (name, text) in documents.iter() {
+            let doc_index = DocIndex::from_text(name, text, schema);
+            let doc_id = docs.add(name);
+            docs.set_length(doc_id, doc_index.length());
+            index.add(doc_index, doc_id);
+        }
+        (docs, index)
+    }
+
+    /// Names each text after its position.
+    fn numbered<'a>(texts: &[&'a str]) -> Vec<(String, &'a str)> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| (i.to_string(), *text))
+            .collect()
+    }
+
+    /// Returns the results of a query, best first.
+    fn results(
+        query: &str,
+        documents: &[(String, &str)],
+        schema: &Schema,
+        options: &SearchOptions,
+    ) -> Vec<QueryResult> {
+        let (docs, index) = build(documents, schema);
+        let query = parse_query(query, schema).unwrap();
+        let mut results = query.search(&index, &docs, &Bm25::default(), options);
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Returns the names of the documents a query matches, best first.
+    fn search(query: &str, texts: &[&str]) -> Vec<String> {
+        search_with(query, texts, &SearchOptions::default())
+    }
+
+    /// Returns the names of the documents a query matches with `options`,
+    /// best first.
+    fn search_with(query: &str, texts: &[&str], options: &SearchOptions) -> Vec<String> {
+        let results = results(query, &numbered(texts), &Schema::default(), options);
+        results.into_iter().map(|result| result.doc_name).collect()
+    }
+
+    /// Returns the names of the documents a query matches, sorted by name.
+    fn matching(query: &str, texts: &[&str]) -> Vec<String> {
+        matching_with(query, texts, &SearchOptions::default())
+    }
+
+    /// Returns the names of the documents a query matches with `options`,
+    /// sorted by name.
+    fn matching_with(query: &str, texts: &[&str], options: &SearchOptions) -> Vec<String> {
+        let mut names = search_with(query, texts, options);
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn fuzzy_ranks_exact_matches_first() {
+        let mut texts = vec!["the hair is long today"; 9];
+        texts.push("a hail storm came");
+        let names = search("hair~1", &texts);
+        assert_eq!(10, names.len());
+        assert_eq!("9", names[9]);
+    }
+
+    #[test]
+    fn exclusion() {
+        let texts = ["steve hair", "long hair", "steve", "hair steve long"];
+        assert_eq!(vec!["1"], matching("hair -steve", &texts));
+        assert_eq!(vec!["0", "1"], matching("hair -\"steve long\"", &texts));
+        assert_eq!(vec!["1"], matching("hair NOT steve", &texts));
+        // On its own, an exclusion matches every other document.
+        assert_eq!(vec!["1"], matching("-steve", &texts));
+        assert_eq!(vec!["1"], matching("NOT steve", &texts));
+        assert_eq!(vec!["1", "2"], matching("NOT hair OR long -steve", &texts));
+    }
+
+    #[test]
+    fn wildcard_expansion_is_capped() {
+        let texts = ["hairy", "hair", "hairline", "haircut", "steve"];
+        assert_eq!(vec!["0", "1", "2", "3"], matching("hair*", &texts));
+        assert_eq!(vec!["1"], matching("h?ir", &texts));
+        assert_eq!(vec!["0"], matching("h?ir?", &texts));
+        // The terms are taken in sorted order: hair, haircut, hairline, hairy.
+        let options = SearchOptions {
+            max_expansions: 2,
+            ..SearchOptions::default()
+        };
+        assert_eq!(vec!["1", "3"], matching_with("hair*", &texts, &options));
+    }
+
+    #[test]
+    fn proximity_slop() {
+        let texts = [
+            "steve hairington",
+            "steve the long hairington",
+            "hairington steve",
+            "steve a b c d e f hairington",
+        ];
+        assert_eq!(vec!["0", "1"], search("\"steve hairington\"~2", &texts));
+        assert_eq!(
+            vec!["0", "1", "3"],
+            matching("\"steve hairington\"~6", &texts)
+        );
+        assert_eq!(
+            vec!["0", "1", "2"],
+            matching("steve NEAR/2 hairington", &texts)
+        );
+        assert_eq!(
+            vec!["0", "1", "2", "3"],
+            matching("steve NEAR/6 hairington", &texts)
+        );
+    }
+
+    #[test]
+    fn closer_terms_rank_higher() {
+        let texts = ["steve x hairington y", "steve hairington x y"];
+        assert_eq!(vec!["1", "0"], search("steve NEAR/3 hairington", &texts));
+        assert_eq!(vec!["1", "0"], search("\"steve hairington\"~3", &texts));
+    }
+
+    #[test]
+    fn stop_words_leave_gaps() {
+        let texts = [
+            "steve the hair",
+            "steve hair",
+            "steve long hair",
+            "steve long thin hair",
+            "steve long thin grey hair",
+        ];
+        let matching = |query: &str| {
+            let schema = Schema::default().with_stop_words();
+            let results = results(query, &numbered(&texts), &schema, &SearchOptions::default());
+            let mut names: Vec<String> =
+                results.into_iter().map(|result| result.doc_name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(vec!["0", "2"], matching("\"steve the hair\""));
+        // Terms closer than the gap count as apart by the difference.
+        assert_eq!(vec!["0", "1", "2", "3"], matching("\"steve the hair\"~1"));
+    }
+
+    #[test]
+    fn closest_match_and_gap() {
+        assert_eq!(
+            Some((5, 9)),
+            closest_match(&[vec![5], vec![2, 9]], &[0, 1], true)
+        );
+        assert_eq!(
+            Some((2, 5)),
+            closest_match(&[vec![5], vec![2, 9]], &[0, 1], false)
+        );
+        assert_eq!(None, closest_match(&[vec![5], vec![2]], &[0, 1], true));
+        // With a stop word between them, adjacent terms are one position off.
+        assert_eq!(
+            Some((0, 1)),
+            closest_match(&[vec![0], vec![1]], &[0, 2], true)
+        );
+        assert_eq!(1, gap((0, 1), &[0, 2]));
+        assert_eq!(0, gap((0, 2), &[0, 2]));
+        assert_eq!(3, gap((0, 4), &[0, 1]));
+    }
+
+    #[test]
+    fn fields_restrict_terms() {
+        let documents: Vec<(String, &str)> = [
+            ("docs/api/intro.md", "error handling"),
+            ("docs/guide.md", "error error"),
+            ("src/main.rs", "error"),
+            ("docs/api/README.txt", "handling"),
+        ]
+        .iter()
+        .map(|(name, text)| (name.to_string(), *text))
+        .collect();
+        let search = |query: &str| {
+            let results = results(
+                query,
+                &documents,
+                &Schema::default(),
+                &SearchOptions::default(),
+            );
+            results
+                .into_iter()
+                .map(|result| (result.doc_name, result.score))
+                .collect::<Vec<_>>()
+        };
+        let names = |query: &str| {
+            let mut names: Vec<String> = search(query).into_iter().map(|(name, _)| name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            vec!["docs/api/intro.md", "docs/guide.md"],
+            names("ext:md error")
+        );
+        assert_eq!(vec!["docs/api/intro.md"], names("path:docs/api error"));
+        assert_eq!(vec!["docs/api/README.txt"], names("name:readme"));
+        assert_eq!(
+            vec!["docs/api/README.txt", "docs/api/intro.md"],
+            names("path:docs/api OR ext:rs handling")
+        );
+        // A field narrows down the results without changing their scores.
+        let error = search("error");
+        for (name, score) in search("ext:md error") {
+            assert!(error.contains(&(name, score)));
+        }
+    }
+
+    #[test]
+    fn regex_scan_is_capped() {
+        let texts = ["aa", "ab", "ac", "ba"];
+        assert_eq!(vec!["0", "1", "2"], matching("/a./", &texts));
+        assert_eq!(vec!["0", "3"], matching("/.a/", &texts));
+        // Only the terms starting with "a" count towards the limit of /a./.
+        let options = SearchOptions {
+            max_regex_scan: 2,
+            ..SearchOptions::default()
+        };
+        assert_eq!(vec!["0", "1"], matching_with("/a./", &texts, &options));
+        assert_eq!(vec!["0"], matching_with("/.a/", &texts, &options));
+        let options = SearchOptions {
+            max_expansions: 1,
+            ..SearchOptions::default()
+        };
+        assert_eq!(vec!["0"], matching_with("/a./", &texts, &options));
+    }
+
+    #[test]
+    fn phrase_frequency_counts_every_occurrence() {
+        let documents = numbered(&[
+            "steve hair long steve hair",
+            "steve hair long long long",
+            "hair hair hair steve hair",
+        ]);
+        let steve_hair = results(
+            "\"steve hair\"",
+            &documents,
+            &Schema::default(),
+            &SearchOptions::default(),
+        );
+        let freqs: Vec<(&str, usize)> = steve_hair
+            .iter()
+            .map(|result| (result.doc_name.as_str(), result.matches[0].term_freq))
+            .collect();
+        assert_eq!(vec![("0", 2), ("1", 1), ("2", 1)], freqs);
+
+        let hair_hair = results(
+            "\"hair hair\"",
+            &documents,
+            &Schema::default(),
+            &SearchOptions::default(),
+        );
+        assert_eq!(1, hair_hair.len());
+        assert_eq!(2, hair_hair[0].matches[0].term_freq);
     }
-    query_results
 }
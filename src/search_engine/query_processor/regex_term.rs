@@ -0,0 +1,91 @@
+use regex::{Error, Regex, RegexBuilder};
+
+/// The most memory, in bytes, a compiled regex can take up.
+///
+/// This keeps patterns like `a{1000}{1000}` from exhausting memory when they
+/// are compiled.
+pub const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Compiles a regex term into a regex that matches whole terms.
+///
+/// An index that lowercases its terms should be searched with `ignore_case`
+/// set, so that `/Hair.*/` still matches the lowercased terms.
+///
+/// # Errors
+/// * If the pattern is not a valid regex, or compiles to more than
+///   `REGEX_SIZE_LIMIT` bytes, then an error is returned.
+pub fn build_regex(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+    RegexBuilder::new(&format!("^(?:{})$", pattern))
+        .case_insensitive(ignore_case)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Returns the literal text every term matching a regex starts with.
+///
+/// Only the terms starting with this prefix need to be checked against the
+/// regex. The prefix is a conservative guess: it stops at the first character
+/// that is not a letter or digit, or that is made optional by a quantifier,
+/// and it is empty if the regex has alternatives outside of any group.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::query_processor::regex_term::literal_prefix;
+///
+/// assert_eq!("hair", literal_prefix("hair(ed|ing)?"));
+/// assert_eq!("hair", literal_prefix("hairs?"));
+/// assert_eq!("", literal_prefix("hair|fur"));
+/// ```
+pub fn literal_prefix(pattern: &str) -> String {
+    if has_top_level_alternation(pattern) {
+        return String::new();
+    }
+    let mut prefix = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_alphanumeric() || matches!(chars.peek(), Some('?' | '*' | '{')) {
+            break;
+        }
+        prefix.push(c);
+    }
+    prefix
+}
+
+/// Returns whether a regex has a `|` outside of any group or class.
+fn has_top_level_alternation(pattern: &str) -> bool {
+    let mut depth = 0;
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class => depth -= 1,
+            '|' if !in_class && depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_prefix_stops_at_the_first_special_character() {
+        assert_eq!("ha", literal_prefix("ha.r"));
+        assert_eq!("hai", literal_prefix("hair{2}"));
+        assert_eq!("hair", literal_prefix("hair+"));
+        assert_eq!("hair", literal_prefix("hair\\|fur"));
+        assert_eq!("hair", literal_prefix("hair[|]"));
+        assert_eq!("", literal_prefix("(hair|fur)s"));
+        assert_eq!("", literal_prefix("hair(ed|ing)|fur"));
+        assert_eq!("", literal_prefix(".*ing"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use crate::search_engine::indexer::doc_table::DocTable;
+
+/// What is known about a term in a document when it is scored.
+///
+/// Every result keeps the matches it was scored from (see
+/// `QueryResult::matches`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TermMatch {
+    /// The term, as it is in the index.
+    pub term: Arc<str>,
+
+    /// The number of times the term occurs in the document.
+    pub term_freq: usize,
+
+    /// The number of documents the term occurs in.
+    pub doc_freq: usize,
+
+    /// The number of documents in the index.
+    pub num_docs: usize,
+
+    /// The length of the document, in terms.
+    pub doc_len: usize,
+
+    /// The average length of a document, in terms.
+    pub avg_doc_len: f64,
+}
+
+impl TermMatch {
+    /// Gathers the statistics of a term that occurs `term_freq` times in a
+    /// document, and in `doc_freq` documents in all, taking the rest from the
+    /// doc table.
+    pub fn new(
+        term: &Arc<str>,
+        term_freq: usize,
+        doc_freq: usize,
+        doc_id: usize,
+        docs: &DocTable,
+    ) -> Self {
+        Self {
+            term: Arc::clone(term),
+            term_freq,
+            doc_freq,
+            num_docs: docs.num_docs(),
+            doc_len: docs.get_length(doc_id),
+            avg_doc_len: docs.avg_length(),
+        }
+    }
+}
+
+/// A way of ranking the documents that match a query.
+///
+/// A scorer scores each term of the query in each document it occurs in.
+/// Phrases and proximity queries add up the scores of their terms and pass
+/// them to `score_proximity`. The score of a document is the sum of the
+/// scores of the clauses of the query it matches; wildcard, fuzzy and regex
+/// terms add up the scores of the terms they expand to.
+///
+/// # Examples
+///
+/// A scorer that only counts whether a term occurs, however often:
+///
+/// ```
+/// use roogle::search_engine::query_processor::scorer::{Scorer, TermMatch};
+///
+/// struct Boolean;
+///
+/// impl Scorer for Boolean {
+///     fn score_term(&self, _: &TermMatch) -> f64 {
+///         1.0
+///     }
+/// }
+/// ```
+///
+/// It is then passed to `QueryProcessor::set_scorer`.
+pub trait Scorer: Send + Sync {
+    /// Scores a term in a document.
+    fn score_term(&self, term: &TermMatch) -> f64;
+
+    /// Scores terms that occur near each other, given the sum of their
+    /// scores and how many other positions were between them, out of the
+    /// `slop` that was allowed.
+    ///
+    /// By default, a match with no gap between the terms scores twice as
+    /// high as one that needs all of the slop; a phrase, with no slop, is not
+    /// raised at all.
+    fn score_proximity(&self, score: f64, slop: usize, gap: usize) -> f64 {
+        score * (1.0 + (slop - gap) as f64 / (slop + 1) as f64)
+    }
+
+    /// Returns the most a term that occurs in `doc_freq` of `num_docs`
+    /// documents can score in any document, if it is bounded at all.
+    ///
+    /// The bound lets `QueryProcessor::search_top_k` skip documents that
+    /// cannot make it into the top results. By default there is none, and
+    /// every matching document is scored.
+    fn max_term_score(&self, _doc_freq: usize, _num_docs: usize) -> Option<f64> {
+        None
+    }
+}
+
+/// Ranks documents by the number of times they contain the terms of the
+/// query.
+///
+/// Terms near each other score one more for every position of slop their
+/// closest match did not need. This favors long documents and ignores how
+/// rare a term is, but is easy to reason about.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermFrequency;
+
+impl Scorer for TermFrequency {
+    fn score_term(&self, term: &TermMatch) -> f64 {
+        term.term_freq as f64
+    }
+
+    fn score_proximity(&self, score: f64, slop: usize, gap: usize) -> f64 {
+        score + (slop - gap) as f64
+    }
+}
+
+/// Ranks documents by the frequency of each term in them, weighted by the
+/// inverse of the number of documents the term occurs in:
+///
+/// ```text
+/// score(t) = tf * (1 + ln((N + 1) / (df(t) + 1)))
+/// ```
+///
+/// where `N` is the number of documents. A term that occurs in every
+/// document still scores its frequency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TfIdf;
+
+impl TfIdf {
+    /// Returns the inverse document frequency of a term that occurs in
+    /// `doc_freq` of `num_docs` documents.
+    pub fn idf(&self, doc_freq: usize, num_docs: usize) -> f64 {
+        1.0 + ((num_docs + 1) as f64 / (doc_freq + 1) as f64).ln()
+    }
+}
+
+impl Scorer for TfIdf {
+    fn score_term(&self, term: &TermMatch) -> f64 {
+        term.term_freq as f64 * self.idf(term.doc_freq.min(term.num_docs), term.num_docs)
+    }
+}
@@ -0,0 +1,36 @@
+/// Options that control how queries are evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// The most terms a single wildcard term (like `hair*`) is expanded to.
+    ///
+    /// The terms matching a wildcard are taken in sorted order, so once the
+    /// cap is reached, the remaining matches are ignored. Fuzzy terms are
+    /// capped the same way, keeping the closest matches.
+    pub max_expansions: usize,
+
+    /// Whether a term that is not in the index is searched for as a fuzzy
+    /// term, so that typos still find the terms they were meant to be.
+    ///
+    /// Terms of up to 2 characters are not corrected, terms of 3 to 5
+    /// characters are corrected by 1 edit, and longer terms by up to 2.
+    pub fuzzy_fallback: bool,
+
+    /// The most terms of the dictionary a single regex term (like
+    /// `/hair(ed|ing)?/`) is checked against.
+    ///
+    /// Only the terms starting with the literal prefix of the regex are
+    /// checked, but a regex without one, like `/.*ing/`, would have to check
+    /// every term. Once the limit is reached, the rest of the dictionary is
+    /// skipped. The terms matching a regex are capped by `max_expansions`.
+    pub max_regex_scan: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            max_expansions: 128,
+            fuzzy_fallback: false,
+            max_regex_scan: 100_000,
+        }
+    }
+}
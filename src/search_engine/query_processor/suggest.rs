@@ -0,0 +1,136 @@
+use crate::search_engine::indexer::index::Index;
+use crate::search_engine::indexer::schema::Schema;
+
+use super::fuzzy::{auto_max_edits, fuzzy_terms};
+use super::query_parser::words;
+use super::query_token;
+
+/// The most corrections considered for each word of a query.
+const MAX_CORRECTIONS: usize = 3;
+
+/// How many times more documents a correction must be in than the rare term
+/// it replaces.
+const RARE_RATIO: usize = 10;
+
+/// The corrections of a word in a query.
+struct Correction<'a> {
+    /// The position of the word in the query, in characters.
+    position: usize,
+
+    /// The length of the word, in characters.
+    len: usize,
+
+    /// The terms the word can be corrected to, with their weights, best
+    /// first.
+    candidates: Vec<(&'a str, f64)>,
+}
+
+/// Returns corrected versions of a query, most likely first.
+///
+/// Every word of the query that is not in the index, or is in fewer than one
+/// in a thousand of the `num_docs` documents, is replaced by the terms within
+/// a few edits of it (see `auto_max_edits`). A correction is weighted by the
+/// number of documents it is in, divided by the square of one more than its
+/// distance, so a common term one edit away beats a rare one that is just as
+/// close. A rare word is only corrected to terms that are in at least ten
+/// times as many documents.
+///
+/// The first suggestion replaces every word with its best correction; the
+/// others each swap one word for a runner-up. Wildcard, fuzzy and field terms
+/// are left alone, and so are malformed queries.
+pub fn suggest<I: Index + ?Sized>(
+    query: &str,
+    schema: &Schema,
+    index: &I,
+    num_docs: usize,
+) -> Vec<String> {
+    let words = match words(query) {
+        Ok(words) => words,
+        Err(_) => return Vec::new(),
+    };
+    let rare = (num_docs / 1000).max(1);
+    let doc_freq = |term: &str| {
+        index
+            .search(term)
+            .map_or(0, |postings| query_token::doc_freq(&postings, index))
+    };
+
+    let mut corrections: Vec<Correction> = Vec::new();
+    for (position, word) in words.into_iter() {
+        if word.contains(['*', '?', '~', ':']) {
+            continue;
+        }
+        let term = match schema.analyze(&word).as_slice() {
+            [(term, _)] => term.clone(),
+            _ => continue,
+        };
+        let term_freq = doc_freq(&term);
+        if term_freq >= rare {
+            continue;
+        }
+        let min_freq = (term_freq * RARE_RATIO).max(1);
+        let mut candidates: Vec<(&str, f64)> = fuzzy_terms(index, &term, auto_max_edits(&term))
+            .into_iter()
+            .filter(|&(candidate, _)| candidate != term)
+            .filter_map(|(candidate, distance)| {
+                let freq = doc_freq(candidate);
+                if freq < min_freq {
+                    return None;
+                }
+                Some((
+                    candidate,
+                    freq as f64 / ((1 + distance) * (1 + distance)) as f64,
+                ))
+            })
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.truncate(MAX_CORRECTIONS);
+        corrections.push(Correction {
+            position,
+            len: word.chars().count(),
+            candidates,
+        });
+    }
+    if corrections.is_empty() {
+        return Vec::new();
+    }
+
+    // Each choice picks a correction for every word: the best for all of
+    // them, then a runner-up for one word at a time.
+    let mut choices: Vec<Vec<usize>> = vec![vec![0; corrections.len()]];
+    for (i, correction) in corrections.iter().enumerate() {
+        for c in 1..correction.candidates.len() {
+            let mut choice = vec![0; corrections.len()];
+            choice[i] = c;
+            choices.push(choice);
+        }
+    }
+    let weight = |choice: &[usize]| -> f64 {
+        corrections
+            .iter()
+            .zip(choice)
+            .map(|(correction, &c)| correction.candidates[c].1)
+            .sum()
+    };
+    choices.sort_by(|a, b| weight(b).total_cmp(&weight(a)));
+
+    // Splice the corrections into the query in place of the words.
+    let chars: Vec<char> = query.chars().collect();
+    choices
+        .iter()
+        .map(|choice| {
+            let mut suggestion = String::new();
+            let mut at = 0;
+            for (correction, &c) in corrections.iter().zip(choice) {
+                suggestion.extend(&chars[at..correction.position]);
+                suggestion.push_str(correction.candidates[c].0);
+                at = correction.position + correction.len;
+            }
+            suggestion.extend(&chars[at..]);
+            suggestion.trim().to_string()
+        })
+        .collect()
+}
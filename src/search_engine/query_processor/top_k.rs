@@ -0,0 +1,315 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+
+use crate::search_engine::indexer::doc_table::DocTable;
+use crate::search_engine::indexer::index::Index;
+use crate::search_engine::indexer::postings::{Cursor, PostingList};
+
+use super::query_parser::Query;
+use super::query_result::QueryResult;
+use super::query_token::{doc_freq, QueryToken};
+use super::scorer::{Scorer, TermMatch};
+
+/// The best results seen so far, up to a fixed number of them.
+///
+/// The results are kept in a min-heap, so the worst of them is the one that
+/// a better result replaces.
+///
+/// # Examples
+///
+/// ```
+/// use roogle::search_engine::query_processor::query_result::QueryResult;
+/// use roogle::search_engine::query_processor::top_k::TopK;
+///
+/// let mut top = TopK::new(2);
+/// top.push(QueryResult::new(1, "a.txt".to_string(), 0.5));
+/// top.push(QueryResult::new(2, "b.txt".to_string(), 2.0));
+/// top.push(QueryResult::new(3, "c.txt".to_string(), 1.0));
+/// assert_eq!(Some(1.0), top.threshold());
+///
+/// let ids: Vec<usize> = top.into_sorted_vec().iter().map(|r| r.doc_id).collect();
+/// assert_eq!(vec![2, 3], ids);
+/// ```
+pub struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<QueryResult>>,
+}
+
+impl TopK {
+    /// Creates a collector of the best `k` results.
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k),
+        }
+    }
+
+    /// Returns the score a result must beat to be kept, once `k` results have
+    /// been collected.
+    pub fn threshold(&self) -> Option<f64> {
+        if self.heap.len() < self.k {
+            return None;
+        }
+        self.heap.peek().map(|worst| worst.0.score)
+    }
+
+    /// Keeps a result if it is among the best `k` seen so far.
+    pub fn push(&mut self, result: QueryResult) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(result));
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if result > worst.0 {
+                *worst = Reverse(result);
+            }
+        }
+    }
+
+    /// Returns the results, best first.
+    pub fn into_sorted_vec(self) -> Vec<QueryResult> {
+        // The heap holds reversed results, so ascending order is best first.
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(result)| result)
+            .collect()
+    }
+}
+
+/// Returns the terms of a query that matches any of its terms, like
+/// `hair OR steve`, or `None` if the query is anything else.
+pub(super) fn disjunctive_terms(query: &Query) -> Option<Vec<&str>> {
+    match query {
+        Query::Token(QueryToken::Term { value }) => Some(vec![value.as_str()]),
+        Query::Or(queries) => {
+            let mut terms: Vec<&str> = Vec::new();
+            for query in queries.iter() {
+                terms.extend(disjunctive_terms(query)?);
+            }
+            Some(terms)
+        }
+        _ => None,
+    }
+}
+
+/// A posting list being walked by `max_score`.
+struct TermCursor<'a> {
+    term: Arc<str>,
+    cursor: Cursor<'a>,
+    doc_freq: usize,
+    max_score: f64,
+}
+
+impl TermCursor<'_> {
+    /// Returns the statistics of the term in the document the cursor is on.
+    fn term_match(&self, docs: &DocTable) -> TermMatch {
+        let doc_id = self.cursor.doc_id().expect("cursor is past the end");
+        let term_freq = self
+            .cursor
+            .positions()
+            .map_or(0, |positions| positions.len());
+        TermMatch::new(&self.term, term_freq, self.doc_freq, doc_id, docs)
+    }
+}
+
+/// Finds the best `k` documents that contain any of the terms, scored as the
+/// union of the terms' results would be.
+///
+/// This is the MaxScore algorithm. The terms are sorted by the most they can
+/// score, and once `k` documents have been found, the terms whose bounds add
+/// up to no more than the worst of them are non-essential: a document that
+/// contains only those terms cannot make it into the results, so only the
+/// documents of the other terms are visited. Non-essential terms are looked
+/// up in those documents, best first, only while the document can still make
+/// it.
+///
+/// Returns `None` if the scorer cannot bound the score of every term (see
+/// `Scorer::max_term_score`).
+pub(super) fn max_score<I: Index + ?Sized>(
+    terms: &[&str],
+    index: &I,
+    docs: &DocTable,
+    scorer: &dyn Scorer,
+    k: usize,
+) -> Option<Vec<QueryResult>> {
+    if k == 0 {
+        return Some(Vec::new());
+    }
+    // Terms that are not in the index match nothing.
+    let postings: Vec<(&str, Cow<PostingList>)> = terms
+        .iter()
+        .filter_map(|&term| index.search(term).map(|postings| (term, postings)))
+        .collect();
+    let mut lists: Vec<TermCursor> = Vec::with_capacity(postings.len());
+    for (term, postings) in postings.iter() {
+        let doc_freq = doc_freq(postings, index);
+        lists.push(TermCursor {
+            term: (*term).into(),
+            cursor: postings.cursor(),
+            doc_freq,
+            max_score: scorer.max_term_score(doc_freq, docs.num_docs())?,
+        });
+    }
+    lists.sort_by(|a, b| a.max_score.total_cmp(&b.max_score));
+    // bounds[i] is the most the terms of lists[..=i] can score together.
+    let bounds: Vec<f64> = lists
+        .iter()
+        .scan(0.0, |sum, list| {
+            *sum += list.max_score;
+            Some(*sum)
+        })
+        .collect();
+
+    let mut top = TopK::new(k);
+    // lists[..essential] are non-essential.
+    let mut essential = 0;
+    // The next candidate is the first document an essential term is in.
+    while let Some(doc_id) = lists[essential..]
+        .iter()
+        .filter_map(|l| l.cursor.doc_id())
+        .min()
+    {
+        let mut score = 0.0;
+        let mut matches: Vec<TermMatch> = Vec::new();
+        for list in lists[essential..].iter_mut() {
+            if list.cursor.doc_id() != Some(doc_id) {
+                continue;
+            }
+            let term = list.term_match(docs);
+            score += scorer.score_term(&term);
+            matches.push(term);
+            list.cursor.advance();
+        }
+        if index.is_deleted(doc_id) {
+            continue;
+        }
+
+        // Every result kept so far has a lower document ID, so a document
+        // that can only tie with the worst of them is not kept either.
+        let threshold = top.threshold();
+        let mut pruned = false;
+        for i in (0..essential).rev() {
+            if threshold.is_some_and(|threshold| score + bounds[i] <= threshold) {
+                pruned = true;
+                break;
+            }
+            let list = &mut lists[i];
+            if list.cursor.seek(doc_id) == Some(doc_id) {
+                let term = list.term_match(docs);
+                score += scorer.score_term(&term);
+                matches.push(term);
+            }
+        }
+        if pruned || threshold.is_some_and(|threshold| score <= threshold) {
+            continue;
+        }
+        top.push(QueryResult::from(doc_id, score, matches, docs));
+
+        // A higher threshold may make more of the terms non-essential.
+        if let Some(threshold) = top.threshold() {
+            while essential < lists.len() && bounds[essential] <= threshold {
+                essential += 1;
+            }
+        }
+    }
+    Some(top.into_sorted_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::indexer::doc_index::DocIndex;
+    use crate::search_engine::indexer::index::IndexWriter;
+    use crate::search_engine::indexer::mem_index::MemIndex;
+    use crate::search_engine::indexer::schema::Schema;
+    use crate::search_engine::query_processor::bm25::Bm25;
+    use crate::search_engine::query_processor::query_parser::parse_query;
+    use crate::search_engine::query_processor::scorer::{TermFrequency, TfIdf};
+    use crate::search_engine::query_processor::search_options::SearchOptions;
+
+    const WORDS: [&str; 8] = [
+        "apple", "banana", "cherry", "date", "elder", "fig", "grape", "hazel",
+    ];
+
+    /// Builds an index of documents of random lengths, where earlier words
+    /// are more common, and deletes a few of them.
+    fn build() -> (DocTable, MemIndex) {
+        let schema = Schema::default();
+        let mut docs = DocTable::new();
+        let mut index = MemIndex::new();
+        let mut seed: u64 = 42;
+        let mut random = |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        for i in 0..300 {
+            let len = 1 + random(40);
+            let text: Vec<&str> = (0..len)
+                .map(|_| WORDS[random(8).min(random(8)) as usize])
+                .collect();
+            let name = format!("{}.txt", i);
+            let doc_index = DocIndex::from_text(&name, &text.join(" "), &schema);
+            let doc_id = docs.add(&name);
+            docs.set_length(doc_id, doc_index.length());
+            index.add(doc_index, doc_id);
+        }
+        for i in (0..300).step_by(7) {
+            let doc_id = docs.remove(&format!("{}.txt", i)).unwrap();
+            index.delete(doc_id);
+        }
+        (docs, index)
+    }
+
+    #[test]
+    fn max_score_matches_search() {
+        let (docs, index) = build();
+        let scorer = Bm25::default();
+        let queries = [
+            "apple",
+            "hazel",
+            "apple OR banana",
+            "grape OR hazel OR apple",
+            "cherry OR date OR elder OR fig",
+            "apple OR banana OR cherry OR date OR elder OR fig OR grape OR hazel",
+            "hazel OR missing",
+        ];
+        for query in queries.iter() {
+            let parsed = parse_query(query, &Schema::default()).unwrap();
+            let terms = disjunctive_terms(&parsed).unwrap();
+            let mut expected = parsed.search(&index, &docs, &scorer, &SearchOptions::default());
+            expected.sort_by(|a, b| b.cmp(a));
+            for k in [0, 1, 2, 5, 10, 50, 1000] {
+                let results = max_score(&terms, &index, &docs, &scorer, k).unwrap();
+                let expected = &expected[..k.min(expected.len())];
+                assert_eq!(expected.len(), results.len(), "{} (k = {})", query, k);
+                for (expected, result) in expected.iter().zip(results.iter()) {
+                    assert_eq!(expected.doc_id, result.doc_id, "{} (k = {})", query, k);
+                    assert!((expected.score - result.score).abs() < 1e-9);
+                }
+            }
+        }
+        // Scorers that cannot bound the score of a term are left to `search`.
+        assert!(max_score(&["apple"], &index, &docs, &TfIdf, 10).is_none());
+        assert!(max_score(&["apple"], &index, &docs, &TermFrequency, 10).is_none());
+    }
+
+    #[test]
+    fn disjunctions_only() {
+        let schema = Schema::default();
+        let terms = |query: &str| {
+            let query = parse_query(query, &schema).unwrap();
+            disjunctive_terms(&query).map(|terms| terms.join(" "))
+        };
+        assert_eq!(Some("hair".to_string()), terms("hair"));
+        assert_eq!(
+            Some("hair steve long".to_string()),
+            terms("hair OR (steve OR long)")
+        );
+        assert_eq!(None, terms("hair steve"));
+        assert_eq!(None, terms("hair OR \"steve hair\""));
+        assert_eq!(None, terms("hair OR -steve"));
+    }
+}
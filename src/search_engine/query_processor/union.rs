@@ -0,0 +1,39 @@
+use std::mem;
+
+use super::query_result::QueryResult;
+
+pub trait Union {
+    /// Adds the results of the other query results vector to this one.
+    ///
+    /// Documents that are in both have their scores and matches merged.
+    fn union(&mut self, other: &[QueryResult]);
+}
+
+impl Union for Vec<QueryResult> {
+    /// Both vectors must be sorted by document ID, and the union stays sorted
+    /// by document ID.
+    ///
+    /// A document that is in both vectors is kept once, with the scores of
+    /// both results added together and their matches combined.
+    fn union(&mut self, other: &[QueryResult]) {
+        let mine = mem::take(self);
+        self.reserve(mine.len().max(other.len()));
+        let mut mine = mine.into_iter().peekable();
+        let mut other = other.iter().peekable();
+        loop {
+            let result = match (mine.peek(), other.peek()) {
+                (Some(a), Some(b)) if a.doc_id < b.doc_id => mine.next().unwrap(),
+                (Some(a), Some(b)) if a.doc_id > b.doc_id => other.next().unwrap().clone(),
+                (Some(_), Some(_)) => {
+                    let mut a = mine.next().unwrap();
+                    a.merge(other.next().unwrap());
+                    a
+                }
+                (Some(_), None) => mine.next().unwrap(),
+                (None, Some(_)) => other.next().unwrap().clone(),
+                (None, None) => break,
+            };
+            self.push(result);
+        }
+    }
+}